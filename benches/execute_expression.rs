@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use expression_engine::{create_context, execute, parse_expression, Value};
+use expression_engine::{compile, create_context, execute, parse_expression, Context, Value};
+use std::collections::HashMap;
 
 fn bench_execute_expression(c: &mut Criterion) {
     let input = "c = 5+3; c+=10+f; c";
@@ -17,10 +18,89 @@ fn bench_execute_expression(c: &mut Criterion) {
     });
 }
 
+fn bench_compiled_expression(c: &mut Criterion) {
+    let input = "c = 5+3; c+=10+f; c";
+    let compiled = compile(input).unwrap();
+    c.bench_function("compiled_expression", |b| {
+        b.iter(|| {
+            compiled.eval(&mut create_context!(
+                "d" => 2,
+                "b" => true,
+                "f" => Arc::new(|_| Ok(Value::from(3)))
+            ))
+        })
+    });
+}
+
+fn bench_execute_nested_list(c: &mut Criterion) {
+    let input = "[".repeat(32) + "1, 'a', {'k': [d, d + 1]}" + &"]".repeat(32);
+    let ast = parse_expression(&input).unwrap();
+    c.bench_function("execute_nested_list", |b| {
+        b.iter(|| {
+            let mut ctx = Context::new();
+            ctx.set_variable("d", Value::from(2));
+            ast.exec(&mut ctx)
+        })
+    });
+}
+
+fn bench_compile_repeated_literals(c: &mut Criterion) {
+    let input = ["level == 'gold' || level == 'silver'"; 64].join(" || ");
+    c.bench_function("compile_repeated_literals", |b| b.iter(|| compile(&input)));
+}
+
+fn bench_map_equality(c: &mut Criterion) {
+    let entries: Vec<(Value, Value)> = (0..10_000)
+        .map(|i| (Value::from(format!("key{}", i)), Value::from(i)))
+        .collect();
+    let a = Value::Map(entries.clone());
+    let b = Value::Map(entries[1..].to_vec());
+    let reversed = Value::Map(entries.iter().rev().take(1_000).cloned().collect());
+    let small = Value::Map(entries[..1_000].to_vec());
+    c.bench_function("map_equality_unequal_length", |bench| bench.iter(|| a == b));
+    c.bench_function("map_equality_reordered", |bench| {
+        bench.iter(|| small == reversed)
+    });
+}
+
+fn bench_read_big_list(c: &mut Criterion) {
+    let ast = parse_expression("big_list").unwrap();
+    let mut ctx = Context::new();
+    ctx.set_variable(
+        "big_list",
+        Value::List((0..10_000).map(Value::from).collect()),
+    );
+    c.bench_function("read_big_list", |b| b.iter(|| ast.exec(&mut ctx)));
+    c.bench_function("read_big_list_borrowed", |b| {
+        b.iter(|| ast.exec_with(&mut ctx, |v| matches!(v, Value::List(l) if !l.is_empty())))
+    });
+}
+
+fn bench_describe(c: &mut Criterion) {
+    let rule = "(a + 1 > b * 2 && f(a, [1, 2, 3])) || c beginWith 'x' ? {a: b} : [a + 1, b - 2]";
+    let input = vec![rule; 32].join("; ");
+    let ast = parse_expression(&input).unwrap();
+    c.bench_function("describe", |b| b.iter(|| ast.describe()));
+    let mut cache = HashMap::new();
+    c.bench_function("describe_cached", |b| {
+        b.iter(|| ast.describe_cached(&mut cache))
+    });
+}
+
 fn bench_parse_expression(c: &mut Criterion) {
     let input = "c = 5+3; c+=10+f; c";
     c.bench_function("parse_expression", |b| b.iter(|| parse_expression(input)));
 }
 
-criterion_group!(benches, bench_execute_expression, bench_parse_expression);
+criterion_group!(
+    benches,
+    bench_execute_expression,
+    bench_compiled_expression,
+    bench_execute_nested_list,
+    bench_compile_repeated_literals,
+    bench_map_equality,
+    bench_read_big_list,
+    bench_describe,
+    bench_parse_expression
+);
 criterion_main!(benches);
@@ -0,0 +1,263 @@
+use crate::context::Context;
+use crate::operator::{InfixOpManager, InfixOpType};
+use crate::parser::{Expr, ExprAST};
+use crate::token::Span;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A suspicious construct found by `analyze`, with the offending sub-expression
+/// and its byte range in the source, which `line_col` maps to lines and columns.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Warning {
+    pub message: String,
+    pub expr: String,
+    pub span: Range<usize>,
+}
+
+// The source spans of the nodes of an AST, keyed by node address.
+struct Spans(HashMap<*const (), Span>);
+
+impl Spans {
+    // Pairs the nodes with the spans recorded by the parser, which are in post-order.
+    fn new(ast: &ExprAST, spans: &[Span]) -> Self {
+        let mut nodes = Vec::with_capacity(spans.len());
+        post_order(ast, &mut nodes);
+        Spans(nodes.into_iter().zip(spans.iter().copied()).collect())
+    }
+
+    fn warning(&self, message: String, expr: &ExprAST) -> Warning {
+        let span = self
+            .0
+            .get(&address(expr))
+            .map_or(0..0, |span| span.0..span.1);
+        Warning {
+            message,
+            expr: expr.expr(),
+            span,
+        }
+    }
+}
+
+fn address(expr: &ExprAST) -> *const () {
+    expr as *const ExprAST as *const ()
+}
+
+fn post_order(expr: &ExprAST, nodes: &mut Vec<*const ()>) {
+    use Expr::*;
+    match expr {
+        Binary(_, lhs, rhs) | Index(lhs, rhs) => {
+            post_order(lhs, nodes);
+            post_order(rhs, nodes);
+        }
+        Ternary(condition, lhs, rhs) => {
+            post_order(condition, nodes);
+            post_order(lhs, nodes);
+            post_order(rhs, nodes);
+        }
+        Unary(_, rhs) | Postfix(rhs, _) => post_order(rhs, nodes),
+        Function(_, exprs) | List(exprs) | Stmt(exprs) => {
+            for expr in exprs {
+                post_order(expr, nodes);
+            }
+        }
+        Map(m) => {
+            for (k, v) in m {
+                post_order(k, nodes);
+                post_order(v, nodes);
+            }
+        }
+        Literal(_) | Reference(_) | None => (),
+    }
+    nodes.push(address(expr));
+}
+
+/// Checks an AST, given the node spans recorded by a parser created with
+/// `Parser::with_spans`.
+pub fn analyze(ast: &ExprAST, spans: &[Span]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    walk(ast, &Spans::new(ast, spans), &mut warnings);
+    warnings
+}
+
+fn walk(expr: &ExprAST, spans: &Spans, warnings: &mut Vec<Warning>) {
+    use Expr::*;
+    match expr {
+        Binary(op, lhs, rhs) => {
+            check_binary(expr, op, lhs, rhs, spans, warnings);
+            walk(lhs, spans, warnings);
+            walk(rhs, spans, warnings);
+        }
+        Ternary(condition, lhs, rhs) => {
+            if let Some(Value::Bool(value)) = eval_constant(condition) {
+                let branch = if value { "else" } else { "then" };
+                warnings.push(spans.warning(
+                    format!(
+                        "condition is always {}, the {} branch is unreachable",
+                        value, branch
+                    ),
+                    expr,
+                ));
+            }
+            walk(condition, spans, warnings);
+            walk(lhs, spans, warnings);
+            walk(rhs, spans, warnings);
+        }
+        Unary(_, rhs) | Postfix(rhs, _) => walk(rhs, spans, warnings),
+        Index(lhs, index) => {
+            walk(lhs, spans, warnings);
+            walk(index, spans, warnings);
+        }
+        Function(_, exprs) | List(exprs) | Stmt(exprs) => {
+            for expr in exprs {
+                walk(expr, spans, warnings);
+            }
+        }
+        Map(m) => {
+            for (k, v) in m {
+                walk(k, spans, warnings);
+                walk(v, spans, warnings);
+            }
+        }
+        Literal(_) | Reference(_) | None => (),
+    }
+}
+
+fn check_binary(
+    expr: &ExprAST,
+    op: &str,
+    lhs: &ExprAST,
+    rhs: &ExprAST,
+    spans: &Spans,
+    warnings: &mut Vec<Warning>,
+) {
+    match op {
+        "==" | "!=" | "<" | "<=" | ">" | ">=" => {
+            let always = if is_constant(lhs) && is_constant(rhs) {
+                match eval_constant(expr) {
+                    Some(Value::Bool(value)) => Some(value),
+                    _ => Option::None,
+                }
+            } else if lhs == rhs && !has_side_effect(lhs) {
+                Some(matches!(op, "==" | "<=" | ">="))
+            } else {
+                Option::None
+            };
+            if let Some(value) = always {
+                warnings.push(spans.warning(format!("comparison is always {}", value), expr));
+            }
+        }
+        "/" | "//" | "%" | "/=" | "%=" | "mod" => {
+            if let Some(Value::Number(value)) = eval_constant(rhs) {
+                if value.is_zero() {
+                    warnings.push(spans.warning("division by zero".to_string(), expr));
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+// Whether the expression only combines literals, so its value is known before execution.
+fn is_constant(expr: &ExprAST) -> bool {
+    use Expr::*;
+    match expr {
+        Literal(_) => true,
+        Unary(_, rhs) | Postfix(rhs, _) => is_constant(rhs),
+        Index(lhs, index) => is_constant(lhs) && is_constant(index),
+        Binary(op, lhs, rhs) => {
+            matches!(InfixOpManager::new().get_op_type(op), Ok(InfixOpType::CALC))
+                && is_constant(lhs)
+                && is_constant(rhs)
+        }
+        Ternary(condition, lhs, rhs) => {
+            is_constant(condition) && is_constant(lhs) && is_constant(rhs)
+        }
+        List(exprs) => exprs.iter().all(is_constant),
+        Map(m) => m.iter().all(|(k, v)| is_constant(k) && is_constant(v)),
+        Function(..) | Reference(_) | Stmt(_) | None => false,
+    }
+}
+
+fn eval_constant(expr: &ExprAST) -> Option<Value> {
+    if !is_constant(expr) {
+        return Option::None;
+    }
+    expr.exec(&mut Context::new()).ok()
+}
+
+// Function calls may return different values each time, and assignments change state.
+fn has_side_effect(expr: &ExprAST) -> bool {
+    use Expr::*;
+    match expr {
+        Function(..) => true,
+        Unary(_, rhs) | Postfix(rhs, _) => has_side_effect(rhs),
+        Index(lhs, index) => has_side_effect(lhs) || has_side_effect(index),
+        Binary(..) if expr.has_assignment() => true,
+        Binary(_, lhs, rhs) => has_side_effect(lhs) || has_side_effect(rhs),
+        Ternary(condition, lhs, rhs) => {
+            has_side_effect(condition) || has_side_effect(lhs) || has_side_effect(rhs)
+        }
+        List(exprs) | Stmt(exprs) => exprs.iter().any(has_side_effect),
+        Map(m) => m
+            .iter()
+            .any(|(k, v)| has_side_effect(k) || has_side_effect(v)),
+        Literal(_) | Reference(_) | None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, Warning};
+    use crate::init::init;
+    use crate::parser::Parser;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("5 > 3", vec![("comparison is always true", "5 > 3", 0..5)])]
+    #[case("1 + 1 != 2", vec![("comparison is always false", "1 + 1 != 2", 0..10)])]
+    #[case("x == x", vec![("comparison is always true", "x == x", 0..6)])]
+    #[case("a.b < a.b", vec![("comparison is always false", "a.b < a.b", 0..9)])]
+    #[case("a / 0", vec![("division by zero", "a / 0", 0..5)])]
+    #[case("a // 0", vec![("division by zero", "a // 0", 0..6)])]
+    #[case("a %= 1 - 1", vec![("division by zero", "a %= 1 - 1", 0..10)])]
+    #[case("b + (a / 0)", vec![("division by zero", "a / 0", 4..11)])]
+    #[case("x = 1;\nx / 0 > 2", vec![("division by zero", "x / 0", 7..12)])]
+    #[case(
+        "true ? a : b",
+        vec![("condition is always true, the else branch is unreachable", "true ? a : b", 0..12)]
+    )]
+    #[case(
+        "[f(2 >= 2)]",
+        vec![("comparison is always true", "2 >= 2", 3..9)]
+    )]
+    #[case(
+        "m[1 / 0] ?? -(x != x)",
+        vec![
+            ("division by zero", "1 / 0", 2..7),
+            ("comparison is always false", "x != x", 13..21),
+        ]
+    )]
+    #[case("x > 3", vec![])]
+    #[case("f() == f()", vec![])]
+    #[case("(x = 1) == (x = 1)", vec![])]
+    #[case("a / b", vec![])]
+    #[case("a > 1 ? a : b", vec![])]
+    fn test_analyze(
+        #[case] input: &str,
+        #[case] output: Vec<(&str, &str, std::ops::Range<usize>)>,
+    ) {
+        init();
+        let mut parser = Parser::with_spans(input).unwrap();
+        let ast = parser.parse_stmt().unwrap();
+        let output: Vec<Warning> = output
+            .into_iter()
+            .map(|(message, expr, span)| Warning {
+                message: message.to_string(),
+                expr: expr.to_string(),
+                span,
+            })
+            .collect();
+        assert_eq!(analyze(&ast, &parser.take_spans()), output);
+    }
+}
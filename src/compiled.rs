@@ -0,0 +1,100 @@
+use crate::context::Context;
+use crate::define::Result;
+use crate::parser::{Expr, Parser};
+use crate::value::Value;
+use std::sync::Arc;
+
+/// An expression parsed once into an owned AST. It doesn't borrow the source
+/// text, so it can be stored and evaluated repeatedly without re-parsing.
+/// Repeated names and string literals share storage.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CompiledExpression {
+    ast: Expr<Arc<str>>,
+}
+
+impl CompiledExpression {
+    pub fn new(expr: &str) -> Result<Self> {
+        let ast = Parser::new(expr)?.parse_stmt()?.to_interned_ast();
+        Ok(Self { ast })
+    }
+
+    pub fn eval(&self, ctx: &mut Context) -> Result<Value> {
+        ctx.reset_steps();
+        self.ast.exec(ctx)
+    }
+
+    pub fn expr(&self) -> String {
+        self.ast.expr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompiledExpression;
+    use crate::context::Context;
+    use crate::init::init;
+    use crate::parser::{Expr, Literal};
+    use crate::value::Value;
+    use rstest::rstest;
+    use std::sync::Arc;
+
+    #[rstest]
+    #[case("a + 1", vec![(1, 2), (5, 6)])]
+    #[case("a > 3 ? 'big' : 'small'", vec![(7, "big"), (2, "small")])]
+    #[case("b = a * 2; b + 1", vec![(3, 7), (0, 1)])]
+    fn test_eval<T: Into<Value>>(#[case] input: &str, #[case] cases: Vec<(i32, T)>) {
+        init();
+        let compiled = CompiledExpression::new(input).unwrap();
+        for (a, output) in cases {
+            let mut ctx = Context::new();
+            ctx.set_variable("a", Value::from(a));
+            assert_eq!(compiled.eval(&mut ctx).unwrap(), output.into());
+        }
+    }
+
+    #[test]
+    fn test_eval_step_limit_per_evaluation() {
+        init();
+        let compiled = CompiledExpression::new("1 + 2 + 3").unwrap();
+        let mut ctx = Context::new();
+        ctx.set_step_limit(Some(10));
+        for _ in 0..5 {
+            assert_eq!(compiled.eval(&mut ctx).unwrap(), Value::from(6));
+        }
+        ctx.set_step_limit(Some(3));
+        assert!(compiled.eval(&mut ctx).is_err());
+    }
+
+    #[test]
+    fn test_interned_strings() {
+        init();
+        let input = ["name == 'vip'"; 4].join(" || ");
+        let compiled = CompiledExpression::new(&input).unwrap();
+        let mut names = vec![];
+        let mut literals = vec![];
+        collect_strings(&compiled.ast, &mut names, &mut literals);
+        assert_eq!(names.len(), 4);
+        assert_eq!(literals.len(), 4);
+        assert!(names.iter().all(|s| Arc::ptr_eq(s, &names[0])));
+        assert!(literals.iter().all(|s| Arc::ptr_eq(s, &literals[0])));
+        let mut ctx = Context::new();
+        ctx.set_variable("name", Value::from("vip"));
+        assert_eq!(compiled.eval(&mut ctx).unwrap(), true.into());
+    }
+
+    fn collect_strings(
+        expr: &Expr<Arc<str>>,
+        names: &mut Vec<Arc<str>>,
+        literals: &mut Vec<Arc<str>>,
+    ) {
+        match expr {
+            Expr::Reference(name) => names.push(name.clone()),
+            Expr::Literal(Literal::String(value)) => literals.push(value.clone()),
+            Expr::Binary(_, lhs, rhs) => {
+                collect_strings(lhs, names, literals);
+                collect_strings(rhs, names, literals);
+            }
+            _ => (),
+        }
+    }
+}
@@ -1,21 +1,172 @@
 use crate::define::Result;
-use crate::function::InnerFunction;
+use crate::error::Error;
+use crate::function::{InnerFunction, InnerFunctionManager};
 use crate::value::Value;
 use core::clone::Clone;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+pub type LazyValue = dyn Fn() -> Result<Value> + Send + Sync + 'static;
 
 #[derive(Clone)]
 pub enum ContextValue {
     Variable(Value),
     Function(Arc<InnerFunction>),
+    Lazy(Arc<LazyValue>),
 }
 
-pub struct Context(pub Arc<Mutex<HashMap<String, ContextValue>>>);
+pub struct Context {
+    pub(crate) store: Arc<Mutex<HashMap<String, ContextValue>>>,
+    default_value: Option<Value>,
+    record: Vec<(Value, Value)>,
+    strict: bool,
+    accessed: Option<Arc<Mutex<Vec<String>>>>,
+    step_limit: Option<usize>,
+    steps: usize,
+    disabled_ops: HashSet<String>,
+}
 
 impl Context {
     pub fn new() -> Self {
-        Context(Arc::new(Mutex::new(HashMap::new())))
+        Context {
+            store: Arc::new(Mutex::new(HashMap::new())),
+            default_value: None,
+            record: Vec::new(),
+            strict: false,
+            accessed: None,
+            step_limit: None,
+            steps: 0,
+            disabled_ops: HashSet::new(),
+        }
+    }
+
+    /// Creates a context bound to the given variables.
+    pub fn from_variables(variables: impl IntoIterator<Item = (String, Value)>) -> Self {
+        let store = variables
+            .into_iter()
+            .map(|(name, value)| (name, ContextValue::Variable(value)))
+            .collect();
+        Context {
+            store: Arc::new(Mutex::new(store)),
+            ..Context::new()
+        }
+    }
+
+    /// Copies the bindings and options into a new context, so changes to either
+    /// don't affect the other.
+    pub fn deep_clone(&self) -> Self {
+        Context {
+            store: Arc::new(Mutex::new(self.store.lock().clone())),
+            accessed: self
+                .accessed
+                .as_ref()
+                .map(|accessed| Arc::new(Mutex::new(accessed.lock().clone()))),
+            ..self.shared_clone()
+        }
+    }
+
+    /// Copies the options into a new context that shares the bindings and the
+    /// access log with this one, so a variable set through either is visible
+    /// through both.
+    pub fn shared_clone(&self) -> Self {
+        Context {
+            store: self.store.clone(),
+            default_value: self.default_value.clone(),
+            record: self.record.clone(),
+            strict: self.strict,
+            accessed: self.accessed.clone(),
+            step_limit: self.step_limit,
+            steps: self.steps,
+            disabled_ops: self.disabled_ops.clone(),
+        }
+    }
+
+    /// Sets the value returned for references that aren't bound in the context.
+    /// Without one, unbound references evaluate to `Value::None`.
+    pub fn set_default_value(&mut self, value: Option<Value>) {
+        self.default_value = value;
+    }
+
+    /// In strict mode, references that aren't bound, aren't record fields and have
+    /// no default value fail with `Error::ReferenceNotExist` instead of evaluating
+    /// to `Value::None`. The left side of `=`, `??=` and `??` may still be unbound.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// While tracking is on, every variable read during execution is logged, in the
+    /// order of first access. References skipped by short-circuiting or by the
+    /// untaken branch of a ternary aren't read, so they aren't logged. Turning
+    /// tracking on starts a new log.
+    pub fn set_access_tracking(&mut self, enabled: bool) {
+        self.accessed = enabled.then(|| Arc::new(Mutex::new(Vec::new())));
+    }
+
+    /// Returns the names of the variables read since tracking was turned on,
+    /// whether or not they were bound.
+    pub fn accessed_variables(&self) -> Vec<String> {
+        self.accessed
+            .as_ref()
+            .map_or_else(Vec::new, |accessed| accessed.lock().clone())
+    }
+
+    pub(crate) fn track(&self, name: &str) {
+        if let Some(accessed) = &self.accessed {
+            let mut accessed = accessed.lock();
+            if !accessed.iter().any(|n| n == name) {
+                accessed.push(name.to_string());
+            }
+        }
+    }
+
+    /// Limits how many nodes each evaluation may visit before failing with
+    /// `Error::StepLimitExceeded`, so a huge or runaway expression can't run for
+    /// long. The count restarts with every evaluation through the crate's entry
+    /// points; `None` removes the limit.
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.step_limit = limit;
+        self.steps = 0;
+    }
+
+    // Starts a new evaluation, with the full step budget.
+    pub(crate) fn reset_steps(&mut self) {
+        self.steps = 0;
+    }
+
+    pub(crate) fn step(&mut self) -> Result<()> {
+        if let Some(limit) = self.step_limit {
+            self.steps += 1;
+            if self.steps > limit {
+                return Err(Error::StepLimitExceeded(limit));
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes evaluation fail with `Error::NotAllowed` when it reaches an operator
+    /// spelled `op`, without unregistering it globally. A symbol registered as
+    /// several kinds of operator, like `-`, is disabled as all of them.
+    pub fn disable_operator(&mut self, op: &str) {
+        self.disabled_ops.insert(op.to_string());
+    }
+
+    pub fn enable_operator(&mut self, op: &str) {
+        self.disabled_ops.remove(op);
+    }
+
+    pub(crate) fn check_operator(&self, op: &str) -> Result<()> {
+        if self.disabled_ops.contains(op) {
+            return Err(Error::NotAllowed(op.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Sets a record whose fields resolve as references, e.g. `price * qty` against
+    /// `{'price': 2, 'qty': 3}`. Bound names take priority over record fields.
+    pub fn set_record(&mut self, record: Value) -> Result<()> {
+        self.record = record.map()?;
+        Ok(())
     }
 
     pub fn set_func(&mut self, name: &str, func: Arc<InnerFunction>) {
@@ -26,41 +177,118 @@ impl Context {
         self.set(name, ContextValue::Variable(value));
     }
 
+    /// Binds a variable computed on its first access. Unlike a function binding,
+    /// the result is cached and later references reuse it.
+    pub fn set_lazy(&mut self, name: &str, init: Arc<LazyValue>) {
+        self.set(name, ContextValue::Lazy(init));
+    }
+
     pub fn set(&mut self, name: &str, v: ContextValue) {
-        self.0.lock().unwrap().insert(name.to_string(), v);
+        self.store.lock().insert(name.to_string(), v);
     }
 
     pub fn get_func(&self, name: &str) -> Option<Arc<InnerFunction>> {
         let value = self.get(name)?;
         match value {
             ContextValue::Function(func) => Some(func.clone()),
-            ContextValue::Variable(_) => None,
+            ContextValue::Variable(Value::Function(func)) => Some(func.clone()),
+            ContextValue::Variable(_) | ContextValue::Lazy(_) => None,
+        }
+    }
+
+    /// Resolves a name passed as an argument to a function the way higher-order
+    /// functions like `map` expect: a function bound with `set_func`, or a
+    /// registered inner function if the name isn't bound or a record field.
+    pub(crate) fn callable(&self, name: &str) -> Option<Arc<InnerFunction>> {
+        match self.get(name) {
+            Some(ContextValue::Function(func)) => Some(func),
+            Some(_) => None,
+            None if self
+                .record
+                .iter()
+                .any(|(k, _)| matches!(k, Value::String(field) if field == name)) =>
+            {
+                None
+            }
+            None => InnerFunctionManager::new().get(name).ok(),
         }
     }
 
     pub fn get_variable(&self, name: &str) -> Option<Value> {
+        self.track(name);
         let value = self.get(name)?;
         match value {
             ContextValue::Variable(v) => Some(v.clone()),
+            ContextValue::Lazy(_) => self.resolve(name, false).ok(),
             ContextValue::Function(_) => None,
         }
     }
 
     pub fn get(&self, name: &str) -> Option<ContextValue> {
-        let binding = self.0.lock().unwrap();
+        let binding = self.store.lock();
         let value = binding.get(name)?;
         Some(value.clone())
     }
 
-    pub fn value(&self, name: &str) -> Result<Value> {
-        let binding = self.0.lock().unwrap();
-        if binding.get(name).is_none() {
-            return Ok(Value::None);
+    /// Exports the variable bindings as a `Value::Map` sorted by name. Functions and
+    /// lazy variables that haven't been computed yet are skipped.
+    pub fn to_map(&self) -> Value {
+        let binding = self.store.lock();
+        let mut names: Vec<&String> = binding.keys().collect();
+        names.sort();
+        let mut m = Vec::new();
+        for name in names {
+            if let ContextValue::Variable(v) = &binding[name] {
+                m.push((Value::from(name.as_str()), v.clone()));
+            }
         }
-        let value = binding.get(name).unwrap();
+        Value::Map(m)
+    }
+
+    pub fn value(&self, name: &str) -> Result<Value> {
+        self.track(name);
+        self.resolve(name, self.strict)
+    }
+
+    // Reads a variable that is allowed to be unbound even in strict mode.
+    pub(crate) fn value_lenient(&self, name: &str) -> Result<Value> {
+        self.track(name);
+        self.resolve(name, false)
+    }
+
+    // Resolves a variable without logging the access. Functions and lazy
+    // initializers run after the bindings are unlocked, so they may read the
+    // context themselves.
+    fn resolve(&self, name: &str, strict: bool) -> Result<Value> {
+        let value = match self.get(name) {
+            Some(value) => value,
+            None => {
+                for (k, v) in &self.record {
+                    if matches!(k, Value::String(field) if field == name) {
+                        return Ok(v.clone());
+                    }
+                }
+                return match &self.default_value {
+                    Some(value) => Ok(value.clone()),
+                    None if strict => Err(Error::ReferenceNotExist(name.to_string())),
+                    None => Ok(Value::None),
+                };
+            }
+        };
         match value {
-            ContextValue::Variable(v) => Ok(v.clone()),
+            ContextValue::Variable(v) => Ok(v),
             ContextValue::Function(func) => func(Vec::new()),
+            ContextValue::Lazy(init) => {
+                let value = init()?;
+                // Unless the name was rebound while `init` ran, later reads reuse
+                // the value.
+                let mut binding = self.store.lock();
+                if matches!(binding.get(name), Some(ContextValue::Lazy(f)) if Arc::ptr_eq(f, &init))
+                {
+                    binding.insert(name.to_string(), ContextValue::Variable(value.clone()));
+                }
+                Ok(value)
+            }
         }
     }
 }
@@ -104,3 +332,262 @@ macro_rules! create_context {
         ctx
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use crate::error::Error;
+    use crate::execute;
+    use crate::init::init;
+    use crate::parser::Parser;
+    use crate::value::Value;
+    use rstest::rstest;
+    use rust_decimal::Decimal;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[rstest]
+    #[case("x + 1", Some(Value::from(0)), Value::from(1))]
+    #[case("x", None, Value::None)]
+    #[case("y = 2; x + y", Some(Value::from(3)), Value::from(5))]
+    #[case("sum + 1", Some(Value::from(0)), Value::from(1))]
+    #[case("type ?? 1", None, Value::from(1))]
+    fn test_default_value(
+        #[case] input: &str,
+        #[case] default_value: Option<Value>,
+        #[case] output: Value,
+    ) {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_default_value(default_value);
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec(&mut ctx).unwrap(), output);
+    }
+
+    #[test]
+    fn test_to_map() {
+        let mut ctx = Context::new();
+        ctx.set_variable("b", Value::from(true));
+        ctx.set_variable("a", Value::from(1));
+        ctx.set_variable("c", Value::List(vec![Value::from("x")]));
+        ctx.set_func("f", Arc::new(|_| Ok(Value::None)));
+        assert_eq!(
+            ctx.to_map(),
+            Value::Map(vec![
+                (Value::from("a"), Value::from(1)),
+                (Value::from("b"), Value::from(true)),
+                (Value::from("c"), Value::List(vec![Value::from("x")])),
+            ])
+        );
+    }
+
+    #[rstest]
+    #[case("price * qty", Value::from(6))]
+    #[case("price = 10; price * qty", Value::from(30))]
+    #[case("discount", Value::None)]
+    fn test_record(#[case] input: &str, #[case] output: Value) {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_record(Value::Map(vec![
+            (Value::from("price"), Value::from(2)),
+            (Value::from("qty"), Value::from(3)),
+            (Value::from(1), Value::from(4)),
+        ]))
+        .unwrap();
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec(&mut ctx).unwrap(), output);
+        assert!(ctx.set_record(Value::from(1)).is_err());
+    }
+
+    #[test]
+    fn test_lazy() {
+        init();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut ctx = Context::new();
+        ctx.set_lazy(
+            "score",
+            Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(Value::from(21))
+            }),
+        );
+        assert_eq!(ctx.to_map(), Value::Map(vec![]));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        let ast = Parser::new("score + score").unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec(&mut ctx).unwrap(), Value::from(42));
+        assert_eq!(ast.exec(&mut ctx).unwrap(), Value::from(42));
+        assert_eq!(ctx.get_variable("score"), Some(Value::from(21)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_lazy_reads_context() {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_variable("a", Value::from(2));
+        let shared = ctx.shared_clone();
+        ctx.set_lazy(
+            "b",
+            Arc::new(move || Ok(Value::from(shared.value("a")?.decimal()? * Decimal::TEN))),
+        );
+        let ast = Parser::new("b + 1").unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec(&mut ctx).unwrap(), Value::from(21));
+        assert_eq!(ctx.get_variable("b"), Some(Value::from(20)));
+    }
+
+    #[test]
+    fn test_lazy_error() {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_lazy("x", Arc::new(|| Err(Error::ParamInvalid())));
+        let ast = Parser::new("x").unwrap().parse_stmt().unwrap();
+        assert!(ast.exec(&mut ctx).is_err());
+        assert_eq!(ctx.get_variable("x"), None);
+    }
+
+    #[test]
+    fn test_assign_skips_bindings() {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_strict(true);
+        ctx.set_lazy("x", Arc::new(|| Err(Error::ParamInvalid())));
+        ctx.set_func("f", Arc::new(|_| Err(Error::ParamInvalid())));
+        let ast = Parser::new("x = 1; f = 2; x + f")
+            .unwrap()
+            .parse_stmt()
+            .unwrap();
+        assert_eq!(ast.exec(&mut ctx).unwrap(), Value::from(3));
+    }
+
+    #[test]
+    fn test_deep_clone() {
+        let mut ctx = Context::from_variables([
+            ("a".to_string(), Value::from(1)),
+            ("b".to_string(), Value::from(2)),
+        ]);
+        let mut copied = ctx.deep_clone();
+        let shared = ctx.shared_clone();
+        copied.set_variable("a", Value::from(10));
+        ctx.set_variable("c", Value::from(3));
+        assert_eq!(ctx.get_variable("a"), Some(Value::from(1)));
+        assert_eq!(copied.get_variable("a"), Some(Value::from(10)));
+        assert_eq!(copied.get_variable("c"), None);
+        assert_eq!(shared.get_variable("c"), Some(Value::from(3)));
+        assert_eq!(copied.get_variable("b"), Some(Value::from(2)));
+    }
+
+    #[rstest]
+    #[case("typo", false, Some(Value::None))]
+    #[case("typo", true, None)]
+    #[case("a + 1", true, Some(Value::from(2)))]
+    #[case("price", true, Some(Value::from(2)))]
+    #[case("b = 2; b", true, Some(Value::from(2)))]
+    #[case("b ??= 3; b", true, Some(Value::from(3)))]
+    #[case("b ?? 4", true, Some(Value::from(4)))]
+    #[case("b += 1", true, None)]
+    #[case("keys", true, None)]
+    #[case("keys", false, Some(Value::None))]
+    fn test_strict(#[case] input: &str, #[case] strict: bool, #[case] output: Option<Value>) {
+        let mut ctx = Context::new();
+        ctx.set_variable("a", Value::from(1));
+        ctx.set_record(Value::Map(vec![(Value::from("price"), Value::from(2))]))
+            .unwrap();
+        ctx.set_strict(strict);
+        let ans = execute(input, ctx);
+        match output {
+            Some(output) => assert_eq!(ans.unwrap(), output),
+            None => assert!(matches!(ans, Err(Error::ReferenceNotExist(_)))),
+        }
+    }
+
+    #[test]
+    fn test_strict_with_default_value() {
+        let mut ctx = Context::new();
+        ctx.set_strict(true);
+        ctx.set_default_value(Some(Value::from(0)));
+        assert_eq!(execute("typo + 1", ctx).unwrap(), Value::from(1));
+    }
+
+    #[rstest]
+    #[case("a && b", vec!["a"])]
+    #[case("b && a", vec!["b", "a"])]
+    #[case("a || b", vec!["a", "b"])]
+    #[case("b ? a : c", vec!["b", "a"])]
+    #[case("a ? c : b", vec!["a", "b"])]
+    #[case("c ?? a", vec!["c", "a"])]
+    #[case("c = a; c + c", vec!["a", "c"])]
+    #[case("max(b, b, missing)", vec!["b", "missing"])]
+    fn test_access_tracking(#[case] input: &str, #[case] accessed: Vec<&str>) {
+        init();
+        let mut ctx = Context::from_variables(vec![
+            ("a".to_string(), Value::from(false)),
+            ("b".to_string(), Value::from(true)),
+        ]);
+        ctx.set_access_tracking(true);
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        ast.exec(&mut ctx).ok();
+        assert_eq!(ctx.accessed_variables(), accessed);
+
+        ctx.set_access_tracking(false);
+        ast.exec(&mut ctx).ok();
+        assert!(ctx.accessed_variables().is_empty());
+    }
+
+    #[rstest]
+    #[case("1 << 2", vec!["<<"], None)]
+    #[case("1 + 2", vec!["<<"], Some(Value::from(3)))]
+    #[case("-a", vec!["-"], None)]
+    #[case("1 - 2", vec!["-"], None)]
+    #[case("1 - 2", vec!["--"], Some(Value::from(-1)))]
+    #[case("a++", vec!["++"], None)]
+    #[case("false && 1 << 2", vec!["<<"], Some(Value::from(false)))]
+    #[case("a += 1", vec!["+="], None)]
+    #[case("not true", vec!["!", "not"], None)]
+    fn test_disable_operator(
+        #[case] input: &str,
+        #[case] disabled: Vec<&str>,
+        #[case] output: Option<Value>,
+    ) {
+        init();
+        let mut ctx = Context::from_variables([("a".to_string(), Value::from(1))]);
+        for op in disabled {
+            ctx.disable_operator(op);
+        }
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        match output {
+            Some(output) => assert_eq!(ast.exec(&mut ctx).unwrap(), output),
+            None => assert!(matches!(ast.exec(&mut ctx), Err(Error::NotAllowed(_)))),
+        }
+    }
+
+    #[test]
+    fn test_enable_operator() {
+        init();
+        let mut ctx = Context::new();
+        ctx.disable_operator("<<");
+        assert!(matches!(
+            execute("1 << 2", ctx.shared_clone()),
+            Err(Error::NotAllowed(op)) if op == "<<"
+        ));
+        ctx.enable_operator("<<");
+        assert_eq!(execute("1 << 2", ctx).unwrap(), Value::from(4));
+    }
+
+    #[rstest]
+    #[case("1 + 2", 3, true)]
+    #[case("1 + 2", 2, false)]
+    #[case("true || a + b + c", 2, true)]
+    #[case("a = 1; a + 1", 6, true)]
+    #[case("a = 1; a + 1", 5, false)]
+    fn test_step_limit(#[case] input: &str, #[case] limit: usize, #[case] ok: bool) {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_step_limit(Some(limit));
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        match ast.exec(&mut ctx) {
+            Err(Error::StepLimitExceeded(n)) => assert!(!ok && n == limit),
+            ans => assert!(ok && ans.is_ok()),
+        }
+    }
+}
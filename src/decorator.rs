@@ -0,0 +1,85 @@
+use crate::registry::{Registry, Store};
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
+
+/// The kind of node a decorator applies to. Operators, functions and
+/// references are decorated by name, the other nodes by kind alone.
+#[derive(Clone, Hash, Eq, PartialEq, Debug)]
+pub enum DecoratorKey {
+    Literal,
+    Unary(String),
+    Binary(String),
+    Postfix(String),
+    Index,
+    Ternary,
+    Function(String),
+    Reference(String),
+    List,
+    Map,
+    Chain,
+    None,
+}
+
+/// A decorator receives the rendered text of a node (its descriptor output,
+/// with decorated children) and returns the text to use in its place.
+type Decorator = dyn Fn(String) -> String + Send + Sync + 'static;
+
+pub struct DecoratorManager {
+    store: Registry<DecoratorKey, Arc<Decorator>>,
+}
+
+impl DecoratorManager {
+    pub fn new() -> Self {
+        static STORE: OnceCell<Store<DecoratorKey, Arc<Decorator>>> = OnceCell::new();
+        DecoratorManager {
+            store: Registry::new(&STORE),
+        }
+    }
+
+    pub fn set_decorator(&mut self, key: DecoratorKey, decorator: Arc<Decorator>) {
+        self.store.set(key, decorator)
+    }
+
+    /// Applies the decorator registered for `key`, or returns `s` unchanged.
+    pub fn decorate(&self, key: &DecoratorKey, s: String) -> String {
+        match self.store.get(key) {
+            Some(f) => f(s),
+            None => s,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecoratorKey, DecoratorManager};
+    use crate::init::init;
+    use crate::parser::Parser;
+    use rstest::rstest;
+    use std::sync::Arc;
+
+    #[rstest]
+    #[case("decorated_ref + 1", "<b>decorated_ref</b>+1")]
+    #[case("[decorated_ref, other]", "[<b>decorated_ref</b>,other]")]
+    #[case(
+        "decoratedFn(decorated_ref)",
+        "call(decoratedFn(<b>decorated_ref</b>))"
+    )]
+    #[case("undecorated * 2", "undecorated*2")]
+    #[case("xs[0] ?? nil", "<i>xs[0]</i>??<null>")]
+    fn test_decorate(#[case] input: &str, #[case] output: &str) {
+        init();
+        let mut m = DecoratorManager::new();
+        m.set_decorator(
+            DecoratorKey::Reference("decorated_ref".to_string()),
+            Arc::new(|s| format!("<b>{}</b>", s)),
+        );
+        m.set_decorator(
+            DecoratorKey::Function("decoratedFn".to_string()),
+            Arc::new(|s| format!("call({})", s)),
+        );
+        m.set_decorator(DecoratorKey::Index, Arc::new(|s| format!("<i>{}</i>", s)));
+        m.set_decorator(DecoratorKey::None, Arc::new(|_| "<null>".to_string()));
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.decorate(), output);
+    }
+}
@@ -1,35 +1,40 @@
+use crate::define::Result;
+use crate::operator::{InfixOpAssociativity, InfixOpConfig, InfixOpManager};
+use crate::registry::{Registry, Store};
 use once_cell::sync::OnceCell;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 #[derive(Hash, Eq, PartialEq)]
 enum DescriptorKey {
-    UNARY(String),
-    BINARY(String),
-    POSTFIX(String),
-    TERNARY,
-    FUNCTION(String),
-    REFERENCE(String),
-    LIST,
-    MAP,
-    CHAIN,
+    Unary(String),
+    Binary(String),
+    Postfix(String),
+    Ternary,
+    Function(String),
+    Reference(String),
+    List,
+    Map,
+    Chain,
+    String,
 }
 
 #[derive(Clone)]
 enum Descriptor {
-    UNARY(Arc<UnaryDescriptor>),
-    BINARY(Arc<BinaryDescriptor>),
-    POSTFIX(Arc<PostfixDescriptor>),
-    TERNARY(Arc<TernaryDescriptor>),
-    FUNCTION(Arc<FunctionDescriptor>),
-    REFERENCE(Arc<ReferenceDescriptor>),
-    LIST(Arc<ListDescriptor>),
-    MAP(Arc<MapDescriptor>),
-    CHAIN(Arc<ChainDescriptor>),
+    Unary(Arc<UnaryDescriptor>),
+    Binary(Arc<BinaryMetaDescriptor>),
+    Postfix(Arc<PostfixDescriptor>),
+    Ternary(Arc<TernaryDescriptor>),
+    Function(Arc<FunctionDescriptor>),
+    Reference(Arc<ReferenceDescriptor>),
+    List(Arc<ListDescriptor>),
+    Map(Arc<MapDescriptor>),
+    Chain(Arc<ChainDescriptor>),
+    String(Arc<StringDescriptor>),
 }
 
 type UnaryDescriptor = dyn Fn(String, String) -> String + Send + Sync + 'static;
 type BinaryDescriptor = dyn Fn(String, String, String) -> String + Send + Sync + 'static;
+type BinaryMetaDescriptor = dyn Fn(BinaryOpMeta, String, String) -> String + Send + Sync + 'static;
 type PostfixDescriptor = dyn Fn(String, String) -> String + Send + Sync + 'static;
 type TernaryDescriptor = dyn Fn(String, String, String) -> String + Send + Sync + 'static;
 type FunctionDescriptor = dyn Fn(String, Vec<String>) -> String + Send + Sync + 'static;
@@ -37,193 +42,371 @@ type ReferenceDescriptor = dyn Fn(String) -> String + Send + Sync + 'static;
 type ListDescriptor = dyn Fn(Vec<String>) -> String + Send + Sync + 'static;
 type MapDescriptor = dyn Fn(Vec<(String, String)>) -> String + Send + Sync + 'static;
 type ChainDescriptor = dyn Fn(Vec<String>) -> String + Send + Sync + 'static;
+type StringDescriptor = dyn Fn(String) -> String + Send + Sync + 'static;
+
+/// The registered configuration of a binary operator, passed to binary
+/// descriptors so they can decide how to render their operands.
+#[derive(Clone)]
+pub struct BinaryOpMeta {
+    pub op: String,
+    pub precedence: i32,
+    pub associativity: InfixOpAssociativity,
+    /// The precedence of the left operand's operator, if it is a binary expression.
+    pub lhs_precedence: Option<i32>,
+    /// The precedence of the right operand's operator, if it is a binary expression.
+    pub rhs_precedence: Option<i32>,
+}
+
+impl BinaryOpMeta {
+    pub fn new(op: &str) -> Self {
+        BinaryOpMeta {
+            op: op.to_string(),
+            precedence: precedence(op),
+            associativity: match InfixOpManager::new().get(op) {
+                Ok(InfixOpConfig(_, _, associativity, _)) => associativity,
+                Err(_) => InfixOpAssociativity::LEFT,
+            },
+            lhs_precedence: None,
+            rhs_precedence: None,
+        }
+    }
+
+    /// Records the operators of the operands, see `parenthesize`.
+    pub fn with_operands(mut self, lhs: Option<&str>, rhs: Option<&str>) -> Self {
+        self.lhs_precedence = lhs.map(precedence);
+        self.rhs_precedence = rhs.map(precedence);
+        self
+    }
+
+    /// Wraps the operands in parentheses where they bind more loosely than this
+    /// operator, so the rendered text keeps the grouping of the expression.
+    pub fn parenthesize(&self, lhs: String, rhs: String) -> (String, String) {
+        let left_assoc = self.associativity == InfixOpAssociativity::LEFT;
+        let wrap = |s: String, precedence: Option<i32>, strict: bool| match precedence {
+            Some(p) if p < self.precedence || (p == self.precedence && !strict) => {
+                format!("({})", s)
+            }
+            _ => s,
+        };
+        (
+            wrap(lhs, self.lhs_precedence, left_assoc),
+            wrap(rhs, self.rhs_precedence, !left_assoc),
+        )
+    }
+}
+
+fn precedence(op: &str) -> i32 {
+    match InfixOpManager::new().get(op) {
+        Ok(InfixOpConfig(precedence, ..)) => precedence,
+        Err(_) => -1,
+    }
+}
 
 pub struct DescriptorManager {
-    store: &'static Mutex<HashMap<DescriptorKey, Descriptor>>,
+    store: Registry<DescriptorKey, Descriptor>,
 }
 
 impl DescriptorManager {
     pub fn new() -> Self {
-        static STORE: OnceCell<Mutex<HashMap<DescriptorKey, Descriptor>>> = OnceCell::new();
-        let store = STORE.get_or_init(|| Mutex::new(HashMap::new()));
-        DescriptorManager { store }
+        static STORE: OnceCell<Store<DescriptorKey, Descriptor>> = OnceCell::new();
+        DescriptorManager {
+            store: Registry::new(&STORE),
+        }
+    }
+
+    /// Creates a manager with its own descriptors, independent of the global
+    /// ones used by `describe` and of other local managers. Render with it via
+    /// `ExprAST::render`.
+    ///
+    /// ``` rust
+    /// use expression_engine::{parse_expression, DescriptorManager};
+    /// use std::sync::Arc;
+    /// let mut lisp = DescriptorManager::local();
+    /// lisp.set_binary_descriptor(
+    ///     "+".to_string(),
+    ///     Arc::new(|op, lhs, rhs| format!("({} {} {})", op, lhs, rhs)),
+    /// );
+    /// let ast = parse_expression("a + b + 1").unwrap();
+    /// assert_eq!(ast.render(&lisp), "(+ (+ a b) 1)");
+    /// assert_eq!(ast.describe(), "a+b+1");
+    /// ```
+    pub fn local() -> Self {
+        DescriptorManager {
+            store: Registry::local(),
+        }
     }
 
     fn set(&mut self, key: DescriptorKey, value: Descriptor) {
-        let mut binding = self.store.lock().unwrap();
-        binding.insert(key, value);
+        self.store.set(key, value)
     }
 
     fn get(&self, key: DescriptorKey) -> Option<Descriptor> {
-        let binding = self.store.lock().unwrap();
-        let value = binding.get(&key);
-        if value.is_none() {
-            return None;
-        }
-        Some(value.unwrap().clone())
+        self.store.get(&key)
     }
 
     pub fn set_unary_descriptor(&mut self, op: String, descriptor: Arc<UnaryDescriptor>) {
-        let key = DescriptorKey::UNARY(op);
-        let value = Descriptor::UNARY(descriptor);
+        let key = DescriptorKey::Unary(op);
+        let value = Descriptor::Unary(descriptor);
         self.set(key, value)
     }
 
     pub fn get_unary_descriptor(&self, op: String) -> Arc<UnaryDescriptor> {
-        let key = DescriptorKey::UNARY(op);
+        let key = DescriptorKey::Unary(op);
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_unary_descriptor);
         }
         match v.unwrap() {
-            Descriptor::UNARY(f) => f.clone(),
+            Descriptor::Unary(f) => f.clone(),
             _ => Arc::new(default_unary_descriptor),
         }
     }
 
     pub fn set_binary_descriptor(&mut self, op: String, descriptor: Arc<BinaryDescriptor>) {
-        let key = DescriptorKey::BINARY(op);
-        let value = Descriptor::BINARY(descriptor);
+        self.set_binary_meta_descriptor(
+            op,
+            Arc::new(move |meta, lhs, rhs| descriptor(meta.op, lhs, rhs)),
+        )
+    }
+
+    /// Like `set_binary_descriptor`, but fails with `InfixOpNotRegistered` if
+    /// `op` isn't a registered infix operator, since its descriptor would never
+    /// be used.
+    pub fn register_binary_descriptor(
+        &mut self,
+        op: String,
+        descriptor: Arc<BinaryDescriptor>,
+    ) -> Result<()> {
+        InfixOpManager::new().get(&op)?;
+        self.set_binary_descriptor(op, descriptor);
+        Ok(())
+    }
+
+    pub fn set_binary_meta_descriptor(
+        &mut self,
+        op: String,
+        descriptor: Arc<BinaryMetaDescriptor>,
+    ) {
+        let key = DescriptorKey::Binary(op);
+        let value = Descriptor::Binary(descriptor);
         self.set(key, value)
     }
 
-    pub fn get_binary_descriptor(&self, op: String) -> Arc<BinaryDescriptor> {
-        let key = DescriptorKey::UNARY(op);
+    pub fn get_binary_descriptor(&self, op: String) -> Arc<BinaryMetaDescriptor> {
+        let key = DescriptorKey::Binary(op);
         let v = self.get(key);
         if v.is_none() {
-            return Arc::new(default_binary_descriptor);
+            return Arc::new(default_binary_meta_descriptor);
         }
         match v.unwrap() {
-            Descriptor::BINARY(f) => f.clone(),
-            _ => Arc::new(default_binary_descriptor),
+            Descriptor::Binary(f) => f.clone(),
+            _ => Arc::new(default_binary_meta_descriptor),
         }
     }
 
     pub fn set_postfix_descriptor(&mut self, op: String, descriptor: Arc<UnaryDescriptor>) {
-        let key = DescriptorKey::POSTFIX(op);
-        let value = Descriptor::POSTFIX(descriptor);
+        let key = DescriptorKey::Postfix(op);
+        let value = Descriptor::Postfix(descriptor);
         self.set(key, value)
     }
 
     pub fn get_postfix_descriptor(&self, op: String) -> Arc<UnaryDescriptor> {
-        let key = DescriptorKey::POSTFIX(op);
+        let key = DescriptorKey::Postfix(op);
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_postfix_descriptor);
         }
         match v.unwrap() {
-            Descriptor::POSTFIX(f) => f.clone(),
+            Descriptor::Postfix(f) => f.clone(),
             _ => Arc::new(default_unary_descriptor),
         }
     }
 
     pub fn set_ternary_descriptor(&mut self, descriptor: Arc<TernaryDescriptor>) {
-        let key = DescriptorKey::TERNARY;
-        let value = Descriptor::TERNARY(descriptor);
+        let key = DescriptorKey::Ternary;
+        let value = Descriptor::Ternary(descriptor);
         self.set(key, value)
     }
 
     pub fn get_ternary_descriptor(&self) -> Arc<TernaryDescriptor> {
-        let key = DescriptorKey::TERNARY;
+        let key = DescriptorKey::Ternary;
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_ternary_descriptor);
         }
         match v.unwrap() {
-            Descriptor::TERNARY(f) => f.clone(),
+            Descriptor::Ternary(f) => f.clone(),
             _ => Arc::new(default_binary_descriptor),
         }
     }
 
     pub fn set_function_descriptor(&mut self, name: String, descriptor: Arc<FunctionDescriptor>) {
-        let key = DescriptorKey::FUNCTION(name);
-        let value = Descriptor::FUNCTION(descriptor);
+        let key = DescriptorKey::Function(name);
+        let value = Descriptor::Function(descriptor);
         self.set(key, value)
     }
 
     pub fn get_function_descriptor(&self, name: String) -> Arc<FunctionDescriptor> {
-        let key = DescriptorKey::FUNCTION(name);
+        let key = DescriptorKey::Function(name);
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_function_descriptor);
         }
         match v.unwrap() {
-            Descriptor::FUNCTION(f) => f.clone(),
+            Descriptor::Function(f) => f.clone(),
             _ => Arc::new(default_function_descriptor),
         }
     }
 
     pub fn set_reference_descriptor(&mut self, name: String, descriptor: Arc<ReferenceDescriptor>) {
-        let key = DescriptorKey::REFERENCE(name);
-        let value = Descriptor::REFERENCE(descriptor);
+        let key = DescriptorKey::Reference(name);
+        let value = Descriptor::Reference(descriptor);
         self.set(key, value)
     }
 
     pub fn get_reference_descriptor(&self, name: String) -> Arc<ReferenceDescriptor> {
-        let key = DescriptorKey::REFERENCE(name);
+        let key = DescriptorKey::Reference(name);
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_reference_descriptor);
         }
         match v.unwrap() {
-            Descriptor::REFERENCE(f) => f.clone(),
+            Descriptor::Reference(f) => f.clone(),
             _ => Arc::new(default_reference_descriptor),
         }
     }
 
     pub fn set_list_descriptor(&mut self, descriptor: Arc<ListDescriptor>) {
-        let key = DescriptorKey::LIST;
-        let value = Descriptor::LIST(descriptor);
+        let key = DescriptorKey::List;
+        let value = Descriptor::List(descriptor);
         self.set(key, value)
     }
 
     pub fn get_list_descriptor(&self) -> Arc<ListDescriptor> {
-        let key = DescriptorKey::LIST;
+        let key = DescriptorKey::List;
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_list_descriptor);
         }
         match v.unwrap() {
-            Descriptor::LIST(f) => f.clone(),
+            Descriptor::List(f) => f.clone(),
             _ => Arc::new(default_list_descriptor),
         }
     }
 
     pub fn set_map_descriptor(&mut self, descriptor: Arc<MapDescriptor>) {
-        let key = DescriptorKey::MAP;
-        let value = Descriptor::MAP(descriptor);
+        let key = DescriptorKey::Map;
+        let value = Descriptor::Map(descriptor);
         self.set(key, value)
     }
 
     pub fn get_map_descriptor(&self) -> Arc<MapDescriptor> {
-        let key = DescriptorKey::MAP;
+        let key = DescriptorKey::Map;
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_map_descriptor);
         }
         match v.unwrap() {
-            Descriptor::MAP(f) => f.clone(),
+            Descriptor::Map(f) => f.clone(),
             _ => Arc::new(default_map_descriptor),
         }
     }
 
     pub fn set_chain_descriptor(&mut self, descriptor: Arc<ChainDescriptor>) {
-        let key = DescriptorKey::CHAIN;
-        let value = Descriptor::CHAIN(descriptor);
+        let key = DescriptorKey::Chain;
+        let value = Descriptor::Chain(descriptor);
         self.set(key, value)
     }
 
     pub fn get_chain_descriptor(&self) -> Arc<ChainDescriptor> {
-        let key = DescriptorKey::CHAIN;
+        let key = DescriptorKey::Chain;
         let v = self.get(key);
         if v.is_none() {
             return Arc::new(default_chain_descriptor);
         }
         match v.unwrap() {
-            Descriptor::CHAIN(f) => f.clone(),
+            Descriptor::Chain(f) => f.clone(),
             _ => Arc::new(default_chain_descriptor),
         }
     }
+
+    pub fn set_string_descriptor(&mut self, descriptor: Arc<StringDescriptor>) {
+        let key = DescriptorKey::String;
+        let value = Descriptor::String(descriptor);
+        self.set(key, value)
+    }
+
+    pub fn get_string_descriptor(&self) -> Arc<StringDescriptor> {
+        let key = DescriptorKey::String;
+        let v = self.get(key);
+        if v.is_none() {
+            return Arc::new(default_string_descriptor);
+        }
+        match v.unwrap() {
+            Descriptor::String(f) => f.clone(),
+            _ => Arc::new(default_string_descriptor),
+        }
+    }
+
+    /// Registers descriptors that render boolean rules as a SQL `WHERE` clause:
+    /// `&&`, `||` and `!` become `AND`, `OR` and `NOT`, `==` and `!=` become `=`
+    /// and `<>`, and strings are single-quoted. Operands are parenthesized where
+    /// the expression grouped them. On the global manager they change what
+    /// `describe` returns, so call `reset` to go back to the default rendering.
+    ///
+    /// ``` rust
+    /// use expression_engine::{parse_expression, DescriptorManager};
+    /// DescriptorManager::new().preset_sql();
+    /// let ast = parse_expression("a == 1 && b > 2").unwrap();
+    /// assert_eq!(ast.describe(), "a = 1 AND b > 2");
+    /// let ast = parse_expression("(a || !b) && name != \"O'Brien\"").unwrap();
+    /// assert_eq!(ast.describe(), "(a OR NOT b) AND name <> 'O''Brien'");
+    /// let ast = parse_expression("a || b && !(c || d)").unwrap();
+    /// assert_eq!(ast.describe(), "a OR b AND NOT (c OR d)");
+    /// DescriptorManager::new().reset();
+    /// assert_eq!(ast.describe(), "a||b&&!c||d");
+    /// ```
+    pub fn preset_sql(&mut self) {
+        for (op, sql_op) in [
+            ("&&", "AND"),
+            ("||", "OR"),
+            ("==", "="),
+            ("!=", "<>"),
+            (">", ">"),
+            (">=", ">="),
+            ("<", "<"),
+            ("<=", "<="),
+        ] {
+            self.set_binary_meta_descriptor(
+                op.to_string(),
+                Arc::new(move |meta, lhs, rhs| {
+                    let (lhs, rhs) = meta.parenthesize(lhs, rhs);
+                    format!("{} {} {}", lhs, sql_op, rhs)
+                }),
+            );
+        }
+        // Unary descriptors don't know what their operand is, so anything but a
+        // single term is parenthesized.
+        for op in ["!", "not"] {
+            self.set_unary_descriptor(
+                op.to_string(),
+                Arc::new(|_, rhs| {
+                    if rhs.contains(' ') {
+                        format!("NOT ({})", rhs)
+                    } else {
+                        format!("NOT {}", rhs)
+                    }
+                }),
+            );
+        }
+        self.set_string_descriptor(Arc::new(|s| format!("'{}'", s.replace('\'', "''"))));
+    }
+
+    /// Removes every registered descriptor, so expressions are described in
+    /// the default format again.
+    pub fn reset(&mut self) {
+        self.store.clear()
+    }
 }
 
 fn default_unary_descriptor(op: String, rhs: String) -> String {
@@ -234,6 +417,10 @@ fn default_binary_descriptor(op: String, lhs: String, rhs: String) -> String {
     lhs + &op + &rhs
 }
 
+fn default_binary_meta_descriptor(meta: BinaryOpMeta, lhs: String, rhs: String) -> String {
+    default_binary_descriptor(meta.op, lhs, rhs)
+}
+
 fn default_postfix_descriptor(lhs: String, op: String) -> String {
     lhs + &op
 }
@@ -266,6 +453,10 @@ fn default_chain_descriptor(params: Vec<String>) -> String {
     params.join(";")
 }
 
+fn default_string_descriptor(s: String) -> String {
+    "\"".to_string() + &s + "\""
+}
+
 #[cfg(test)]
 mod tests {
     use super::default_binary_descriptor;
@@ -277,7 +468,14 @@ mod tests {
     use super::default_reference_descriptor;
     use super::default_ternary_descriptor;
     use super::default_unary_descriptor;
-    use super::DescriptorManager;
+    use super::{BinaryOpMeta, DescriptorManager};
+    use crate::error::Error;
+    use crate::init::init;
+    use crate::operator::{InfixOpAssociativity, InfixOpManager, InfixOpType};
+    use crate::parser::Parser;
+    use crate::value::Value;
+    use rstest::rstest;
+    use std::collections::HashMap;
     use std::sync::Arc;
 
     #[test]
@@ -297,4 +495,128 @@ mod tests {
         DescriptorManager::new()
             .set_postfix_descriptor("haha".to_string(), Arc::new(default_postfix_descriptor))
     }
+
+    #[rstest]
+    #[case("a plus b", "(a plus b)")]
+    #[case("a plus b times c", "(a plus b times c)")]
+    #[case("(a plus b) times c", "(a plus b) times c")]
+    #[case("a times b joined c", "a times b ~ c")]
+    fn test_binary_meta_descriptor(#[case] input: &str, #[case] output: &str) {
+        init();
+        let mut manager = InfixOpManager::new();
+        for (op, precedence) in [("plus", 110), ("times", 120), ("joined", 100)] {
+            manager.register(
+                op,
+                precedence,
+                InfixOpType::CALC,
+                InfixOpAssociativity::LEFT,
+                Arc::new(|_, _| Ok(Value::None)),
+            );
+        }
+        for op in ["plus", "times"] {
+            DescriptorManager::new().set_binary_meta_descriptor(
+                op.to_string(),
+                Arc::new(|meta, lhs, rhs| {
+                    let s = format!("{} {} {}", lhs, meta.op, rhs);
+                    if meta.precedence < 120 {
+                        return format!("({})", s);
+                    }
+                    s
+                }),
+            );
+        }
+        DescriptorManager::new().set_binary_descriptor(
+            "joined".to_string(),
+            Arc::new(|_, lhs, rhs| format!("{} ~ {}", lhs, rhs)),
+        );
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.describe(), output);
+    }
+
+    #[test]
+    fn test_get_binary_descriptor() {
+        init();
+        InfixOpManager::new().register(
+            "glue",
+            110,
+            InfixOpType::CALC,
+            InfixOpAssociativity::LEFT,
+            Arc::new(|_, _| Ok(Value::None)),
+        );
+        DescriptorManager::new().set_binary_descriptor(
+            "glue".to_string(),
+            Arc::new(|op, lhs, rhs| format!("{}({}, {})", op, lhs, rhs)),
+        );
+        let descriptor = DescriptorManager::new().get_binary_descriptor("glue".to_string());
+        assert_eq!(
+            descriptor(BinaryOpMeta::new("glue"), "a".to_string(), "b".to_string()),
+            "glue(a, b)"
+        );
+        let ast = Parser::new("a glue b").unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.describe(), "glue(a, b)");
+    }
+
+    #[test]
+    fn test_register_binary_descriptor() {
+        init();
+        let mut manager = DescriptorManager::local();
+        let ans = manager.register_binary_descriptor(
+            "xyz".to_string(),
+            Arc::new(|_, lhs, rhs| format!("xyz({}, {})", lhs, rhs)),
+        );
+        assert!(matches!(ans, Err(Error::InfixOpNotRegistered(op)) if op == "xyz"));
+        assert!(manager
+            .register_binary_descriptor(
+                "+".to_string(),
+                Arc::new(|_, lhs, rhs| format!("plus({}, {})", lhs, rhs)),
+            )
+            .is_ok());
+        let ast = Parser::new("a + b").unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.render(&manager), "plus(a, b)");
+    }
+
+    #[rstest]
+    #[case("outer(inner(x))", "OUTER<inner{x}>")]
+    #[case("outer(inner(inner(x)), y)", "OUTER<inner{inner{x}}, y>")]
+    #[case("inner(outer(x)) + 1", "inner{OUTER<x>}+1")]
+    fn test_nested_function_descriptor(#[case] input: &str, #[case] output: &str) {
+        init();
+        DescriptorManager::new().set_function_descriptor(
+            "outer".to_string(),
+            Arc::new(|_, params| format!("OUTER<{}>", params.join(", "))),
+        );
+        DescriptorManager::new().set_function_descriptor(
+            "inner".to_string(),
+            Arc::new(|name, params| format!("{}{{{}}}", name, params.join(", "))),
+        );
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.describe(), output);
+        assert_eq!(ast.describe_cached(&mut HashMap::new()), output);
+    }
+
+    #[rstest]
+    #[case("a && !b", "(and a (not b))", "a AND NOT b")]
+    #[case("f(a, 'x')", "(f a \"x\")", "f(a,'x')")]
+    #[case("[1, a]", "(list 1 a)", "[1,a]")]
+    fn test_render_with_local_managers(#[case] input: &str, #[case] lisp: &str, #[case] sql: &str) {
+        init();
+        let mut lisp_manager = DescriptorManager::local();
+        lisp_manager.set_binary_descriptor(
+            "&&".to_string(),
+            Arc::new(|_, lhs, rhs| format!("(and {} {})", lhs, rhs)),
+        );
+        lisp_manager
+            .set_unary_descriptor("!".to_string(), Arc::new(|_, rhs| format!("(not {})", rhs)));
+        lisp_manager.set_function_descriptor(
+            "f".to_string(),
+            Arc::new(|name, params| format!("({} {})", name, params.join(" "))),
+        );
+        lisp_manager.set_list_descriptor(Arc::new(|params| format!("(list {})", params.join(" "))));
+        let mut sql_manager = DescriptorManager::local();
+        sql_manager.preset_sql();
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.render(&lisp_manager), lisp);
+        assert_eq!(ast.render(&sql_manager), sql);
+        assert_eq!(ast.describe(), ast.render(&DescriptorManager::new()));
+    }
 }
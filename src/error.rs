@@ -30,6 +30,22 @@ pub enum Error {
     InvalidInteger,
     InvalidFloat,
     ExpectBinOpToken,
+    AssignmentNotAllowed,
+    NotInteger(String),
+    IntegerOutOfRange(String),
+    DivideByZero,
+    InvalidRegex(String, String),
+    NotComparable(String, String),
+    EvalContext(String, Box<Error>),
+    InvalidOperands(String, String, String),
+    InvalidDate(String),
+    Custom(String),
+    IndexOutOfRange(i64),
+    UnexpectedType(String, String),
+    ExpressionTooDeep(usize),
+    Overflow(String),
+    StepLimitExceeded(usize),
+    NotAllowed(String),
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -67,6 +83,41 @@ impl fmt::Display for Error {
             InvalidInteger => write!(f, "invalid integer"),
             InvalidFloat => write!(f, "invalid float"),
             ExpectBinOpToken => write!(f, "expect bin op token"),
+            AssignmentNotAllowed => write!(f, "assignment not allowed"),
+            NotInteger(s) => write!(f, "not an integer: {}", s),
+            IntegerOutOfRange(s) => write!(f, "integer out of range: {}", s),
+            DivideByZero => write!(f, "divide by zero"),
+            InvalidRegex(pattern, err) => write!(f, "invalid regex {}: {}", pattern, err),
+            NotComparable(a, b) => write!(f, "not comparable: {} and {}", a, b),
+            EvalContext(expr, err) => write!(f, "{} in expression: {}", err, expr),
+            InvalidOperands(op, a, b) => write!(f, "invalid operands for {}: {} and {}", op, a, b),
+            InvalidDate(s) => write!(f, "invalid date: {}", s),
+            Custom(s) => write!(f, "{}", s),
+            IndexOutOfRange(index) => write!(f, "index out of range: {}", index),
+            ExpressionTooDeep(max_depth) => {
+                write!(f, "expression too deep: exceeds {} levels", max_depth)
+            }
+            UnexpectedType(expected, actual) => {
+                write!(f, "unexpected type: expected {}, got {}", expected, actual)
+            }
+            Overflow(op) => write!(f, "arithmetic overflow in {}", op),
+            StepLimitExceeded(limit) => write!(f, "step limit exceeded: {} steps", limit),
+            NotAllowed(op) => write!(f, "operator not allowed: {}", op),
         }
     }
 }
+
+// Long rules are cut down so that logged errors stay readable.
+const EVAL_CONTEXT_MAX_CHARS: usize = 64;
+
+impl Error {
+    /// Wraps the error with a snippet of the expression that failed to evaluate.
+    pub(crate) fn in_expression(self, expr: &str) -> Error {
+        let expr = expr.trim();
+        let snippet = match expr.char_indices().nth(EVAL_CONTEXT_MAX_CHARS) {
+            Some((end, _)) => format!("{}...", &expr[..end]),
+            None => expr.to_string(),
+        };
+        Error::EvalContext(snippet, Box::new(self))
+    }
+}
@@ -2,9 +2,11 @@ use crate::define::Result;
 use crate::error::Error;
 use crate::value::Value;
 use once_cell::sync::OnceCell;
-use rust_decimal::Decimal;
+use parking_lot::Mutex;
+use rust_decimal::prelude::*;
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 pub type InnerFunction = dyn Fn(Vec<Value>) -> Result<Value> + Send + Sync + 'static;
 
@@ -16,7 +18,7 @@ impl InnerFunctionManager {
     pub fn new() -> Self {
         static STORE: OnceCell<Mutex<HashMap<String, Arc<InnerFunction>>>> = OnceCell::new();
         let store = STORE.get_or_init(|| Mutex::new(HashMap::new()));
-        InnerFunctionManager { store: store }
+        InnerFunctionManager { store }
     }
 
     pub fn init(&mut self) {
@@ -24,13 +26,13 @@ impl InnerFunctionManager {
             "min",
             Arc::new(|params| {
                 let mut min = None;
-                for param in params.into_iter() {
+                for param in spread_params(params)? {
                     let num = param.decimal()?;
                     if min.is_none() || num < min.unwrap() {
                         min = Some(num);
                     }
                 }
-                Ok(Value::Number(min.unwrap()))
+                min.map(Value::Number).ok_or(Error::ParamInvalid())
             }),
         );
 
@@ -38,13 +40,13 @@ impl InnerFunctionManager {
             "max",
             Arc::new(|params| {
                 let mut max = None;
-                for param in params.into_iter() {
+                for param in spread_params(params)? {
                     let num = param.decimal()?;
                     if max.is_none() || num > max.unwrap() {
                         max = Some(num);
                     }
                 }
-                Ok(Value::Number(max.unwrap()))
+                max.map(Value::Number).ok_or(Error::ParamInvalid())
             }),
         );
 
@@ -52,7 +54,7 @@ impl InnerFunctionManager {
             "sum",
             Arc::new(|params| {
                 let mut ans = Decimal::ZERO;
-                for param in params.into_iter() {
+                for param in spread_params(params)? {
                     ans += param.decimal()?;
                 }
                 Ok(Value::Number(ans))
@@ -63,24 +65,1326 @@ impl InnerFunctionManager {
             "mul",
             Arc::new(|params| {
                 let mut ans = Decimal::ONE;
-                for param in params.into_iter() {
+                for param in spread_params(params)? {
                     ans *= param.decimal()?;
                 }
                 Ok(Value::Number(ans))
             }),
         );
+
+        self.register(
+            "median",
+            Arc::new(|params| {
+                let [list] = fixed_params(params)?;
+                let mut nums = numeric_list(list)?;
+                nums.sort();
+                let mid = nums.len() / 2;
+                if nums.len() % 2 == 1 {
+                    return Ok(Value::Number(nums[mid]));
+                }
+                // The difference can only overflow when both have the same sign,
+                // and the sum only when they don't.
+                let (a, b) = (nums[mid - 1], nums[mid]);
+                let ans = match b.checked_sub(a) {
+                    Some(diff) => a.checked_add(diff / Decimal::TWO),
+                    None => a.checked_add(b).map(|sum| sum / Decimal::TWO),
+                };
+                ans.map(Value::Number)
+                    .ok_or_else(|| Error::Overflow("median".to_string()))
+            }),
+        );
+
+        // The population standard deviation.
+        self.register(
+            "stddev",
+            Arc::new(|params| {
+                let [list] = fixed_params(params)?;
+                let nums = numeric_list(list)?;
+                let overflow = || Error::Overflow("stddev".to_string());
+                let len = Decimal::from(nums.len());
+                let mut sum = Decimal::ZERO;
+                for num in &nums {
+                    sum = sum.checked_add(*num).ok_or_else(overflow)?;
+                }
+                let mean = sum / len;
+                let mut squares = Decimal::ZERO;
+                for num in &nums {
+                    let diff = num.checked_sub(mean).ok_or_else(overflow)?;
+                    squares = diff
+                        .checked_mul(diff)
+                        .and_then(|square| squares.checked_add(square))
+                        .ok_or_else(overflow)?;
+                }
+                (squares / len)
+                    .sqrt()
+                    .map(|ans| Value::Number(ans.normalize()))
+                    .ok_or(Error::ParamInvalid())
+            }),
+        );
+
+        // Euclidean modulo, which is never negative, unlike `%` whose result takes
+        // the sign of the dividend and the `mod` operator, which takes the divisor's.
+        self.register(
+            "mod",
+            Arc::new(|params| {
+                let [a, b] = fixed_params(params)?;
+                let (a, b) = (a.decimal()?, b.decimal()?);
+                let r = a.checked_rem(b).ok_or(Error::DivideByZero)?;
+                if r.is_sign_negative() && !r.is_zero() {
+                    return Ok(Value::Number(r + b.abs()));
+                }
+                Ok(Value::Number(r))
+            }),
+        );
+
+        // Rounds half to even by default, so round(2.5) is 2 and round(3.5) is
+        // 4. The optional mode picks 'half_up', 'half_even' or 'down' instead.
+        self.register(
+            "round",
+            Arc::new(|params| {
+                let mut params = params.into_iter();
+                let value = params.next().ok_or(Error::ParamInvalid())?.decimal()?;
+                let places = match params.next() {
+                    Some(places) => {
+                        u32::try_from(places.integer()?).map_err(|_| Error::ParamInvalid())?
+                    }
+                    None => 0,
+                };
+                let strategy = match params.next() {
+                    Some(mode) => match mode.string()?.as_str() {
+                        "half_up" => RoundingStrategy::MidpointAwayFromZero,
+                        "half_even" => RoundingStrategy::MidpointNearestEven,
+                        "down" => RoundingStrategy::ToZero,
+                        _ => return Err(Error::ParamInvalid()),
+                    },
+                    None => RoundingStrategy::MidpointNearestEven,
+                };
+                if params.next().is_some() {
+                    return Err(Error::ParamInvalid());
+                }
+                Ok(Value::Number(
+                    value.round_dp_with_strategy(places, strategy),
+                ))
+            }),
+        );
+
+        self.register(
+            "parseNumber",
+            Arc::new(|params| {
+                let mut params = params.into_iter();
+                let input = params.next().ok_or(Error::ParamInvalid())?.string()?;
+                let decimal_separator = match params.next() {
+                    Some(value) => value.string()?,
+                    None => ".".to_string(),
+                };
+                let group_separator = match params.next() {
+                    Some(value) => value.string()?,
+                    None if decimal_separator == "," => ".".to_string(),
+                    None => ",".to_string(),
+                };
+                if params.next().is_some()
+                    || decimal_separator.is_empty()
+                    || decimal_separator == group_separator
+                {
+                    return Err(Error::ParamInvalid());
+                }
+                let normalized = input
+                    .trim()
+                    .replace(&group_separator, "")
+                    .replace(&decimal_separator, ".");
+                Decimal::from_str(&normalized)
+                    .map(Value::Number)
+                    .map_err(|_| Error::InvalidNumber(input))
+            }),
+        );
+
+        self.register(
+            "hasKey",
+            Arc::new(|params| {
+                let [m, key] = fixed_params(params)?;
+                Ok(Value::from(m.map()?.iter().any(|(k, _)| *k == key)))
+            }),
+        );
+
+        // A key that is present keeps its value even when it's None.
+        self.register(
+            "getOrDefault",
+            Arc::new(|params| {
+                let [m, key, default] = fixed_params(params)?;
+                Ok(m.map()?
+                    .into_iter()
+                    .find(|(k, _)| *k == key)
+                    .map_or(default, |(_, v)| v))
+            }),
+        );
+
+        self.register(
+            "hasValue",
+            Arc::new(|params| {
+                let [m, value] = fixed_params(params)?;
+                Ok(Value::from(m.map()?.iter().any(|(_, v)| *v == value)))
+            }),
+        );
+
+        self.register(
+            "keys",
+            Arc::new(|params| {
+                let [m] = fixed_params(params)?;
+                Ok(Value::List(m.map()?.into_iter().map(|(k, _)| k).collect()))
+            }),
+        );
+
+        self.register(
+            "values",
+            Arc::new(|params| {
+                let [m] = fixed_params(params)?;
+                Ok(Value::List(m.map()?.into_iter().map(|(_, v)| v).collect()))
+            }),
+        );
+
+        // Keeps the first of equal elements, so maps equal up to entry order count
+        // as duplicates.
+        self.register(
+            "unique",
+            Arc::new(|params| {
+                let [list] = fixed_params(params)?;
+                let mut ans: Vec<Value> = Vec::new();
+                for value in list.list()? {
+                    if !ans.contains(&value) {
+                        ans.push(value);
+                    }
+                }
+                Ok(Value::List(ans))
+            }),
+        );
+
+        self.register(
+            "reverse",
+            Arc::new(|params| {
+                let [list] = fixed_params(params)?;
+                let mut list = list.list()?;
+                list.reverse();
+                Ok(Value::List(list))
+            }),
+        );
+
+        // Entries keep the order of the pairs, like a map literal.
+        self.register(
+            "toMap",
+            Arc::new(|params| {
+                let [pairs] = fixed_params(params)?;
+                let mut m = Vec::new();
+                for pair in pairs.list()? {
+                    let [k, v] = fixed_params(pair.list()?)?;
+                    m.push((k, v));
+                }
+                Ok(Value::Map(m))
+            }),
+        );
+
+        // None counts as empty, so an unset collection doesn't need a separate check.
+        self.register(
+            "isEmpty",
+            Arc::new(|params| {
+                let [value] = fixed_params(params)?;
+                match value {
+                    Value::String(s) => Ok(Value::from(s.is_empty())),
+                    Value::List(list) => Ok(Value::from(list.is_empty())),
+                    Value::Map(m) => Ok(Value::from(m.is_empty())),
+                    Value::None => Ok(Value::from(true)),
+                    _ => Err(Error::ParamInvalid()),
+                }
+            }),
+        );
+
+        self.register(
+            "type",
+            Arc::new(|params| {
+                let [value] = fixed_params(params)?;
+                Ok(Value::from(value.type_name()))
+            }),
+        );
+
+        for (name, type_name) in [
+            ("isNumber", "number"),
+            ("isString", "string"),
+            ("isBool", "bool"),
+            ("isList", "list"),
+            ("isMap", "map"),
+        ] {
+            self.register(
+                name,
+                Arc::new(move |params| {
+                    let [value] = fixed_params(params)?;
+                    Ok(Value::from(value.matches_type(type_name)))
+                }),
+            );
+        }
+
+        self.register(
+            "assertType",
+            Arc::new(|params| {
+                let [value, type_name] = fixed_params(params)?;
+                let type_name = type_name.string()?;
+                if !value.matches_type(&type_name) {
+                    return Err(Error::UnexpectedType(
+                        type_name,
+                        value.type_name().to_string(),
+                    ));
+                }
+                Ok(value)
+            }),
+        );
+
+        self.register(
+            "map",
+            Arc::new(|params| {
+                let [list, f] = fixed_params(params)?;
+                let f = callable(f)?;
+                let mut ans = Vec::new();
+                for value in list.list()? {
+                    ans.push(f(vec![value])?);
+                }
+                Ok(Value::List(ans))
+            }),
+        );
+
+        self.register(
+            "filter",
+            Arc::new(|params| {
+                let [list, f] = fixed_params(params)?;
+                let f = callable(f)?;
+                let mut ans = Vec::new();
+                for value in list.list()? {
+                    if f(vec![value.clone()])?.bool()? {
+                        ans.push(value);
+                    }
+                }
+                Ok(Value::List(ans))
+            }),
+        );
+
+        // Sorting is stable, and keys must be numbers or strings of one type.
+        self.register(
+            "sortBy",
+            Arc::new(|params| {
+                let [list, f] = fixed_params(params)?;
+                sort_by_keys(list, vec![callable(f)?])
+            }),
+        );
+
+        // Elements with equal keys are ordered by the next key function.
+        self.register(
+            "sortByKeys",
+            Arc::new(|params| {
+                let [list, fs] = fixed_params(params)?;
+                let fs = fs
+                    .list()?
+                    .into_iter()
+                    .map(callable)
+                    .collect::<Result<_>>()?;
+                sort_by_keys(list, fs)
+            }),
+        );
+
+        // A result that isn't a list is appended as a single element.
+        self.register(
+            "flatMap",
+            Arc::new(|params| {
+                let [list, f] = fixed_params(params)?;
+                let f = callable(f)?;
+                let mut ans = Vec::new();
+                for value in list.list()? {
+                    match f(vec![value])? {
+                        Value::List(values) => ans.extend(values),
+                        value => ans.push(value),
+                    }
+                }
+                Ok(Value::List(ans))
+            }),
+        );
+
+        self.register(
+            "currency",
+            Arc::new(|params| {
+                let [value, symbol, places] = fixed_params(params)?;
+                let places = u32::try_from(places.integer()?)
+                    .ok()
+                    .filter(|places| *places <= 28)
+                    .ok_or(Error::ParamInvalid())?;
+                Ok(Value::from(format_currency(
+                    value.decimal()?,
+                    &symbol.string()?,
+                    places,
+                )))
+            }),
+        );
+
+        #[cfg(feature = "chrono")]
+        self.register(
+            "now",
+            Arc::new(|params| {
+                let [] = fixed_params(params)?;
+                Ok(Value::from(chrono::Utc::now()))
+            }),
+        );
+
+        #[cfg(feature = "chrono")]
+        self.register(
+            "parseDate",
+            Arc::new(|params| {
+                let mut params = params.into_iter();
+                let input = params.next().ok_or(Error::ParamInvalid())?.string()?;
+                let fmt = params.next().map(Value::string).transpose()?;
+                if params.next().is_some() {
+                    return Err(Error::ParamInvalid());
+                }
+                parse_date(&input, fmt.as_deref())
+            }),
+        );
+
+        #[cfg(feature = "chrono")]
+        for (name, unit) in [
+            ("seconds", 1),
+            ("minutes", 60),
+            ("hours", 3600),
+            ("days", 86400),
+        ] {
+            self.register(
+                name,
+                Arc::new(move |params| {
+                    let [n] = fixed_params(params)?;
+                    let seconds = n
+                        .decimal()?
+                        .checked_mul(Decimal::from(unit))
+                        .ok_or_else(|| Error::Overflow(name.to_string()))?;
+                    let seconds = Value::from(seconds).integer()?;
+                    Ok(Value::Duration(seconds))
+                }),
+            );
+        }
+
+        #[cfg(feature = "serde_json")]
+        self.register(
+            "parseJson",
+            Arc::new(|params| {
+                let [s] = fixed_params(params)?;
+                Value::from_json_str(&s.string()?)
+            }),
+        );
+
+        #[cfg(feature = "serde_json")]
+        self.register(
+            "toJson",
+            Arc::new(|params| {
+                let [v] = fixed_params(params)?;
+                check_json_keys(&v)?;
+                Ok(Value::String(v.to_json().to_string()))
+            }),
+        );
+
+        // An empty separator splits the string into its characters.
+        self.register(
+            "split",
+            Arc::new(|params| {
+                let [s, separator] = fixed_params(params)?;
+                let (s, separator) = (s.string()?, separator.string()?);
+                let parts: Vec<Value> = if separator.is_empty() {
+                    s.chars().map(|ch| Value::from(ch.to_string())).collect()
+                } else {
+                    s.split(separator.as_str()).map(Value::from).collect()
+                };
+                Ok(Value::List(parts))
+            }),
+        );
+
+        self.register(
+            "join",
+            Arc::new(|params| {
+                let [list, separator] = fixed_params(params)?;
+                let separator = separator.string()?;
+                let parts = list
+                    .list()?
+                    .into_iter()
+                    .map(join_part)
+                    .collect::<Result<Vec<String>>>()?;
+                Ok(Value::from(parts.join(&separator)))
+            }),
+        );
+
+        // Returns the first argument that isn't None, like a chain of `??`.
+        self.register(
+            "default",
+            Arc::new(|params| {
+                if params.is_empty() {
+                    return Err(Error::ParamInvalid());
+                }
+                Ok(params
+                    .into_iter()
+                    .find(|v| *v != Value::None)
+                    .unwrap_or(Value::None))
+            }),
+        );
+
+        // Unlike `??` and `default`, which only skip None, this also falls back on
+        // false, zero and empty strings, lists and maps.
+        self.register(
+            "orElse",
+            Arc::new(|params| {
+                let [value, fallback] = fixed_params(params)?;
+                Ok(if is_falsy(&value) { fallback } else { value })
+            }),
+        );
+
+        self.register(
+            "concat",
+            Arc::new(|params| {
+                let parts = params
+                    .into_iter()
+                    .map(join_part)
+                    .collect::<Result<Vec<String>>>()?;
+                Ok(Value::from(parts.concat()))
+            }),
+        );
+
+        // Each `{}` takes the next argument, and `{{` and `}}` escape the braces.
+        self.register(
+            "format",
+            Arc::new(|params| {
+                let mut params = params.into_iter();
+                let template = params.next().ok_or(Error::ParamInvalid())?.string()?;
+                let mut ans = String::new();
+                let mut chars = template.chars().peekable();
+                while let Some(ch) = chars.next() {
+                    match (ch, chars.peek()) {
+                        ('{', Some('}')) => {
+                            chars.next();
+                            let value = params.next().ok_or(Error::ParamInvalid())?;
+                            ans.push_str(&format_part(&value, true));
+                        }
+                        ('{', Some('{')) | ('}', Some('}')) => {
+                            chars.next();
+                            ans.push(ch);
+                        }
+                        ('{' | '}', _) => return Err(Error::ParamInvalid()),
+                        _ => ans.push(ch),
+                    }
+                }
+                if params.next().is_some() {
+                    return Err(Error::ParamInvalid());
+                }
+                Ok(Value::from(ans))
+            }),
+        );
+
+        // Positions count characters in strings and elements in lists.
+        self.register(
+            "indexOf",
+            Arc::new(|params| {
+                let [haystack, needle] = fixed_params(params)?;
+                let index = match haystack {
+                    Value::String(s) => {
+                        let needle = needle.string()?;
+                        s.find(&needle).map(|i| s[..i].chars().count())
+                    }
+                    Value::List(values) => values.iter().position(|value| *value == needle),
+                    _ => return Err(Error::ParamInvalid()),
+                };
+                Ok(index.map_or(Value::from(-1), |i| Value::from(i as i64)))
+            }),
+        );
+
+        self.register(
+            "slice",
+            Arc::new(|params| {
+                let mut params = params.into_iter();
+                let value = params.next().ok_or(Error::ParamInvalid())?;
+                let start = params.next().ok_or(Error::ParamInvalid())?.integer()?;
+                let end = params.next().map(Value::integer).transpose()?;
+                if params.next().is_some() {
+                    return Err(Error::ParamInvalid());
+                }
+                match value {
+                    Value::String(s) => {
+                        let len = s.chars().count();
+                        let (start, end) = slice_range(len, start, end);
+                        Ok(Value::from(
+                            s.chars().skip(start).take(end - start).collect::<String>(),
+                        ))
+                    }
+                    Value::List(values) => {
+                        let (start, end) = slice_range(values.len(), start, end);
+                        Ok(Value::List(values[start..end].to_vec()))
+                    }
+                    _ => Err(Error::ParamInvalid()),
+                }
+            }),
+        );
+
+        self.register(
+            "replace",
+            Arc::new(|params| {
+                let [s, from, to] = fixed_params(params)?;
+                Ok(Value::from(
+                    s.string()?.replace(&from.string()?, &to.string()?),
+                ))
+            }),
+        );
+
+        self.register(
+            "replaceFirst",
+            Arc::new(|params| {
+                let [s, from, to] = fixed_params(params)?;
+                Ok(Value::from(s.string()?.replacen(
+                    &from.string()?,
+                    &to.string()?,
+                    1,
+                )))
+            }),
+        );
     }
 
     pub fn register(&mut self, name: &str, f: Arc<InnerFunction>) {
-        self.store.lock().unwrap().insert(name.to_string(), f);
+        self.store.lock().insert(name.to_string(), f);
     }
 
     pub fn get(&self, name: &str) -> Result<Arc<InnerFunction>> {
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         let ans = binding.get(name);
         if ans.is_none() {
             return Err(Error::InnerFunctionNotRegistered(String::from(name)));
         }
         Ok(ans.unwrap().clone())
     }
+
+    pub fn functions(&self) -> Vec<String> {
+        let mut ans: Vec<String> = self.store.lock().keys().cloned().collect();
+        ans.sort();
+        ans
+    }
+}
+
+// A single list argument stands for its elements, so f([1, 2]) is the same as f(1, 2).
+// The list must not be empty.
+fn spread_params(params: Vec<Value>) -> Result<Vec<Value>> {
+    match <[Value; 1]>::try_from(params) {
+        Ok([Value::List(values)]) if values.is_empty() => Err(Error::ParamInvalid()),
+        Ok([Value::List(values)]) => Ok(values),
+        Ok([value]) => Ok(vec![value]),
+        Err(params) => Ok(params),
+    }
+}
+
+// Negative bounds count from the end, and bounds out of range are clamped, so the
+// range is always valid even if empty.
+fn slice_range(len: usize, start: i64, end: Option<i64>) -> (usize, usize) {
+    let clamp = |i: i64| {
+        let i = if i < 0 { i + len as i64 } else { i };
+        i.clamp(0, len as i64) as usize
+    };
+    let start = clamp(start);
+    let end = end.map_or(len, clamp);
+    (start, end.max(start))
+}
+
+// Higher-order functions take a function value, or the name of a registered
+// inner function as a string.
+fn callable(value: Value) -> Result<Arc<InnerFunction>> {
+    match value {
+        Value::Function(f) => Ok(f),
+        Value::String(name) => InnerFunctionManager::new().get(&name),
+        _ => Err(Error::ParamInvalid()),
+    }
+}
+
+fn sort_by_keys(list: Value, fs: Vec<Arc<InnerFunction>>) -> Result<Value> {
+    let mut keyed = Vec::new();
+    for value in list.list()? {
+        let keys = fs
+            .iter()
+            .map(|f| f(vec![value.clone()]))
+            .collect::<Result<Vec<_>>>()?;
+        keyed.push((keys, value));
+    }
+    let mut err = None;
+    keyed.sort_by(|(a, _), (b, _)| {
+        for (a, b) in a.iter().zip(b) {
+            match a.compare(b) {
+                Ok(Ordering::Equal) => continue,
+                Ok(ordering) => return ordering,
+                Err(e) => {
+                    err.get_or_insert(e);
+                    return Ordering::Equal;
+                }
+            }
+        }
+        Ordering::Equal
+    });
+    match err {
+        Some(e) => Err(e),
+        None => Ok(Value::List(keyed.into_iter().map(|(_, v)| v).collect())),
+    }
+}
+
+fn is_falsy(value: &Value) -> bool {
+    match value {
+        Value::None => true,
+        Value::Bool(b) => !b,
+        Value::Number(num) => num.is_zero(),
+        Value::String(s) => s.is_empty(),
+        Value::List(list) => list.is_empty(),
+        Value::Map(m) => m.is_empty(),
+        _ => false,
+    }
+}
+
+// The numbers of a non-empty list, for the statistical functions.
+fn numeric_list(value: Value) -> Result<Vec<Decimal>> {
+    let list = value.list()?;
+    if list.is_empty() {
+        return Err(Error::ParamInvalid());
+    }
+    list.into_iter().map(|value| value.decimal()).collect()
+}
+
+fn fixed_params<const N: usize>(params: Vec<Value>) -> Result<[Value; N]> {
+    params.try_into().map_err(|_| Error::ParamInvalid())
+}
+
+// Only scalars can be joined, numbers and bools are written the way they are parsed.
+fn join_part(value: Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(Error::ParamInvalid()),
+    }
+}
+
+// Writes any value for `format`. Top-level strings are written as they are, and
+// the ones nested in lists and maps are quoted like literals.
+fn format_part(value: &Value, top: bool) -> String {
+    match value {
+        Value::String(s) if top => s.clone(),
+        Value::String(s) => format!("'{}'", s),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::List(values) => {
+            let parts: Vec<String> = values.iter().map(|v| format_part(v, false)).collect();
+            format!("[{}]", parts.join(", "))
+        }
+        Value::Map(m) => {
+            let parts: Vec<String> = m
+                .iter()
+                .map(|(k, v)| format!("{}: {}", format_part(k, false), format_part(v, false)))
+                .collect();
+            format!("{{{}}}", parts.join(", "))
+        }
+        Value::None => "nil".to_string(),
+        Value::Function(_) => "function".to_string(),
+        #[cfg(feature = "chrono")]
+        Value::DateTime(ms) => chrono::DateTime::from_timestamp_millis(*ms)
+            .map_or_else(|| ms.to_string(), |datetime| datetime.to_rfc3339()),
+        #[cfg(feature = "chrono")]
+        Value::Duration(seconds) => format!("{}s", seconds),
+    }
+}
+
+// JSON objects only have string keys, so maps keyed by anything else are rejected.
+#[cfg(feature = "serde_json")]
+fn check_json_keys(value: &Value) -> Result<()> {
+    match value {
+        Value::List(values) => values.iter().try_for_each(check_json_keys),
+        Value::Map(m) => m.iter().try_for_each(|(k, v)| match k {
+            Value::String(_) => check_json_keys(v),
+            _ => Err(Error::ShouldBeString()),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Without a format, RFC 3339 and ISO 8601 dates with or without a time are accepted.
+// Dates without an offset are taken as UTC, and dates without a time as midnight.
+#[cfg(feature = "chrono")]
+fn parse_date(input: &str, fmt: Option<&str>) -> Result<Value> {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+    let parsed = match fmt {
+        Some(fmt) => DateTime::parse_from_str(input, fmt)
+            .map(|datetime| datetime.to_utc())
+            .or_else(|_| NaiveDateTime::parse_from_str(input, fmt).map(|dt| dt.and_utc()))
+            .or_else(|_| {
+                NaiveDate::parse_from_str(input, fmt).map(|d| d.and_time(NaiveTime::MIN).and_utc())
+            }),
+        None => DateTime::parse_from_rfc3339(input)
+            .map(|datetime| datetime.to_utc())
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M:%S").map(|dt| dt.and_utc())
+            })
+            .or_else(|_| {
+                NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                    .map(|d| d.and_time(NaiveTime::MIN).and_utc())
+            }),
+    };
+    parsed
+        .map(Value::from)
+        .map_err(|_| Error::InvalidDate(input.to_string()))
+}
+
+// Rounds half away from zero and groups the integer digits by thousands, e.g. -$1,234.50.
+fn format_currency(value: Decimal, symbol: &str, places: u32) -> String {
+    let rounded = value.round_dp_with_strategy(places, RoundingStrategy::MidpointAwayFromZero);
+    let digits = format!("{:.*}", places as usize, rounded.abs());
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits.as_str(), None),
+    };
+    let mut ans = String::new();
+    if rounded.is_sign_negative() && !rounded.is_zero() {
+        ans.push('-');
+    }
+    ans.push_str(symbol);
+    for (i, ch) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            ans.push(',');
+        }
+        ans.push(ch);
+    }
+    if let Some(fraction) = fraction {
+        ans.push('.');
+        ans.push_str(fraction);
+    }
+    ans
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{execute, register_function, Context, Error, Value};
+    use rstest::rstest;
+    use rust_decimal::prelude::*;
+    use std::sync::Arc;
+
+    #[rstest]
+    #[case("min(3, 1, 2)", 1.into())]
+    #[case("min([3, 1, 2])", 1.into())]
+    #[case("min(5)", 5.into())]
+    #[case("max(3, 1, 2)", 3.into())]
+    #[case("max([3, 1, 2])", 3.into())]
+    #[case("sum(1, 2, 3)", 6.into())]
+    #[case("sum([1, 2, 3])", 6.into())]
+    #[case("mul(2, 3, 4)", 24.into())]
+    #[case("mul([2, 3, 4])", 24.into())]
+    #[case("median([1,2,3,4])", Decimal::from_str("2.5").unwrap().into())]
+    #[case("median([3, 1, 2])", 2.into())]
+    #[case("median([7])", 7.into())]
+    #[case("median([79228162514264337593543950335, 79228162514264337593543950335])", Decimal::MAX.into())]
+    #[case("median([-79228162514264337593543950335, 79228162514264337593543950335])", 0.into())]
+    #[case("median([-3, -2])", Decimal::from_str("-2.5").unwrap().into())]
+    #[case("stddev([2, 4, 4, 4, 5, 5, 7, 9])", 2.into())]
+    #[case("stddev([1, 2])", Decimal::from_str("0.5").unwrap().into())]
+    #[case("stddev([3])", 0.into())]
+    #[case("-5 % 3", (-2).into())]
+    #[case("mod(-5, 3)", 1.into())]
+    #[case("mod(5, -3)", 2.into())]
+    #[case("mod(-5, -3)", 1.into())]
+    #[case("mod(5.5, 2)", Decimal::from_str("1.5").unwrap().into())]
+    #[case("mod(6, 3)", 0.into())]
+    #[case("5 mod -3", (-1).into())]
+    #[case("mod(-7, 3) + 1 mod 3", 3.into())]
+    #[case("round(3.14159, 2)", Decimal::from_str("3.14").unwrap().into())]
+    #[case("round(2.5)", 2.into())]
+    #[case("round(3.5)", 4.into())]
+    #[case("round(-1.005, 2)", Decimal::from_str("-1.00").unwrap().into())]
+    #[case("round(12.5, 0)", 12.into())]
+    #[case("round(1.23, 5)", Decimal::from_str("1.23").unwrap().into())]
+    #[case("round(2.5, 0, 'half_up')", 3.into())]
+    #[case("round(2.5, 0, 'half_even')", 2.into())]
+    #[case("round(-2.5, 0, 'half_up')", (-3).into())]
+    #[case("round(2.59, 1, 'down')", Decimal::from_str("2.5").unwrap().into())]
+    #[case("round(-2.59, 1, 'down')", Decimal::from_str("-2.5").unwrap().into())]
+    #[case("parseNumber('1234.56')", Decimal::from_str("1234.56").unwrap().into())]
+    #[case("parseNumber(' 1,234.56 ')", Decimal::from_str("1234.56").unwrap().into())]
+    #[case("parseNumber('1.234,56', ',', '.')", Decimal::from_str("1234.56").unwrap().into())]
+    #[case("parseNumber('1 234,56', ',', ' ')", Decimal::from_str("1234.56").unwrap().into())]
+    #[case("parseNumber('3,14', ',')", Decimal::from_str("3.14").unwrap().into())]
+    #[case("parseNumber('-42')", (-42).into())]
+    #[case("hasKey({'a': 1, 2: 'b'}, 'a')", true.into())]
+    #[case("hasKey({'a': 1, 2: 'b'}, 2.0)", true.into())]
+    #[case("hasKey({'a': 1, 2: 'b'}, 'b')", false.into())]
+    #[case("hasKey({}, 'a')", false.into())]
+    #[case("getOrDefault({'a': 1, 2: 'b'}, 'a', 0)", 1.into())]
+    #[case("getOrDefault({'a': 1, 2: 'b'}, 2.0, 'c')", "b".into())]
+    #[case("getOrDefault({'a': 1, 2: 'b'}, 'z', 0)", 0.into())]
+    #[case("getOrDefault({'a': nil}, 'a', 0)", Value::None)]
+    #[case("getOrDefault({}, 'a', [1])", vec![Value::from(1)].into())]
+    #[case("hasValue({'a': 1, 2: 'b'}, 'b')", true.into())]
+    #[case("hasValue({'a': 1, 2: 'b'}, 1.00)", true.into())]
+    #[case("hasValue({'a': 1, 2: 'b'}, 'a')", false.into())]
+    #[case("keys({'b': 1, 'a': 2, 3: true})", Value::List(vec!["b".into(), "a".into(), 3.into()]))]
+    #[case("keys({})", Value::List(vec![]))]
+    #[case("values({'b': 1, 'a': 2, 3: true})", Value::List(vec![1.into(), 2.into(), true.into()]))]
+    #[case("values({})", Value::List(vec![]))]
+    #[case("unique([1, 2, 1, 3, 2])", Value::List(vec![1.into(), 2.into(), 3.into()]))]
+    #[case("unique([1, 1.0, '1'])", Value::List(vec![1.into(), "1".into()]))]
+    #[case("unique([{'a': 1, 'b': 2}, {'b': 2, 'a': 1}, {'a': 1}]) == [{'a': 1, 'b': 2}, {'a': 1}]", true.into())]
+    #[case("unique([])", Value::List(vec![]))]
+    #[case("reverse([1, 'a', true])", Value::List(vec![true.into(), "a".into(), 1.into()]))]
+    #[case("reverse([])", Value::List(vec![]))]
+    #[case("toMap([['a',1],['b',2]])", Value::Map(vec![("a".into(), 1.into()), ("b".into(), 2.into())]))]
+    #[case("toMap([[1, [true]]])", Value::Map(vec![(1.into(), Value::List(vec![true.into()]))]))]
+    #[case("toMap([]) == {}", true.into())]
+    #[case("toMap([['a', 1]]) == {'a': 1}", true.into())]
+    #[case("isEmpty('')", true.into())]
+    #[case("isEmpty('a')", false.into())]
+    #[case("isEmpty([])", true.into())]
+    #[case("isEmpty([nil])", false.into())]
+    #[case("isEmpty({})", true.into())]
+    #[case("isEmpty({'a': 1})", false.into())]
+    #[case("isEmpty(nil)", true.into())]
+    #[case("isEmpty(unset)", true.into())]
+    #[case("orElse('', 'x')", "x".into())]
+    #[case("orElse(0, 5)", 5.into())]
+    #[case("orElse(0.00, 5)", 5.into())]
+    #[case("orElse(false, true)", true.into())]
+    #[case("orElse([], [1])", Value::List(vec![1.into()]))]
+    #[case("orElse({}, 1)", 1.into())]
+    #[case("orElse(unset, 1)", 1.into())]
+    #[case("orElse('a', 'x')", "a".into())]
+    #[case("orElse(-1, 5)", (-1).into())]
+    #[case("orElse([0], 5)", Value::List(vec![0.into()]))]
+    #[case("'' ?? 'x'", "".into())]
+    #[case("type(1.5)", "number".into())]
+    #[case("type('haha')", "string".into())]
+    #[case("type(false)", "bool".into())]
+    #[case("type([1, 'a'])", "list".into())]
+    #[case("type({'a': 1})", "map".into())]
+    #[case("type(unset)", "none".into())]
+    #[case("flatMap([1, 2], 'flat_dup')", Value::List(vec![1.into(), 1.into(), 2.into(), 2.into()]))]
+    #[case("flatMap([1, 2], 'flat_inc')", Value::List(vec![2.into(), 3.into()]))]
+    #[case("flatMap([], 'flat_dup')", Value::List(vec![]))]
+    #[case("flatMap([1, 2], flat_dup)", Value::List(vec![1.into(), 1.into(), 2.into(), 2.into()]))]
+    #[case("map([1, 2, 3], flat_inc)", Value::List(vec![2.into(), 3.into(), 4.into()]))]
+    #[case("map([1, 2], 'flat_inc')", Value::List(vec![2.into(), 3.into()]))]
+    #[case("map([[1, 2], [3]], sum)", Value::List(vec![3.into(), 3.into()]))]
+    #[case("map([], flat_inc)", Value::List(vec![]))]
+    #[case("filter([1, 'a', 2], isNumber)", Value::List(vec![1.into(), 2.into()]))]
+    #[case("type(flat_inc)", "function".into())]
+    #[case("currency(1234.5, '$', 2)", "$1,234.50".into())]
+    #[case("currency(-1234567.891, '€', 2)", "-€1,234,567.89".into())]
+    #[case("currency(999.995, '$', 2)", "$1,000.00".into())]
+    #[case("currency(12, '', 0)", "12".into())]
+    #[case("currency(123456, '¥', 0)", "¥123,456".into())]
+    #[case("currency(-0.001, '$', 2)", "$0.00".into())]
+    #[case("split('a,b,c', ',')", Value::List(vec!["a".into(), "b".into(), "c".into()]))]
+    #[case("split('a::b', '::')", Value::List(vec!["a".into(), "b".into()]))]
+    #[case("split('abc', '')", Value::List(vec!["a".into(), "b".into(), "c".into()]))]
+    #[case("split('', ',')", Value::List(vec!["".into()]))]
+    #[case("join(['a', 'b'], '-')", "a-b".into())]
+    #[case("join(['a', 1.5, true], ', ')", "a, 1.5, true".into())]
+    #[case("join([], '-')", "".into())]
+    #[case("join(split('a,b', ','), ';')", "a;b".into())]
+    #[case("indexOf('hello', 'l')", 2.into())]
+    #[case("indexOf('héllo', 'l')", 2.into())]
+    #[case("indexOf('hello', 'x')", (-1).into())]
+    #[case("indexOf('hello', '')", 0.into())]
+    #[case("indexOf([1, 'a', 2], 2)", 2.into())]
+    #[case("indexOf([1, 'a'], 'b')", (-1).into())]
+    #[case("slice('hello', 1, 3)", "el".into())]
+    #[case("slice('hello', -3)", "llo".into())]
+    #[case("slice('héllo', 0, -2)", "hél".into())]
+    #[case("slice('hello', 2, 100)", "llo".into())]
+    #[case("slice('hello', -100, 2)", "he".into())]
+    #[case("slice('hello', 4, 1)", "".into())]
+    #[case("slice([1, 2, 3, 4], 1, 3)", Value::List(vec![2.into(), 3.into()]))]
+    #[case("slice([1, 2, 3, 4], -2)", Value::List(vec![3.into(), 4.into()]))]
+    #[case("slice([1, 2, 3], 5, 9)", Value::List(vec![]))]
+    #[case("slice([1, 2, 3], -9, 9)", Value::List(vec![1.into(), 2.into(), 3.into()]))]
+    #[case("replace('aaa', 'a', 'b')", "bbb".into())]
+    #[case("replaceFirst('aaa', 'a', 'b')", "baa".into())]
+    #[case("replace('a-b-c', '-', '')", "abc".into())]
+    #[case("replaceFirst('abc', 'x', 'y')", "abc".into())]
+    fn test_exec(#[case] input: &str, #[case] output: Value) {
+        register_function(
+            "flat_dup",
+            Arc::new(|params| Ok(Value::List(vec![params[0].clone(), params[0].clone()]))),
+        );
+        register_function(
+            "flat_inc",
+            Arc::new(|params| Ok(Value::from(params[0].clone().decimal()? + Decimal::ONE))),
+        );
+        let ans = execute(input, Context::new());
+        assert!(ans.is_ok());
+        assert_eq!(ans.unwrap(), output);
+    }
+
+    #[rstest]
+    #[case("min()")]
+    #[case("min([])")]
+    #[case("max([])")]
+    #[case("max([1, 2], 3)")]
+    #[case("sum([1, 'a'])")]
+    #[case("sum([])")]
+    #[case("mul([])")]
+    #[case("mod(1, 0)")]
+    #[case("mod(1)")]
+    #[case("mod('a', 2)")]
+    #[case("keys()")]
+    #[case("keys([1, 2])")]
+    #[case("keys({}, {})")]
+    #[case("values('a')")]
+    #[case("unique()")]
+    #[case("unique('aba')")]
+    #[case("reverse('abc')")]
+    #[case("reverse([1], [2])")]
+    #[case("toMap()")]
+    #[case("toMap({'a': 1})")]
+    #[case("toMap([['a']])")]
+    #[case("toMap([['a', 1, 2]])")]
+    #[case("toMap(['a', 1])")]
+    #[case("isEmpty()")]
+    #[case("orElse(1)")]
+    #[case("orElse(1, 2, 3)")]
+    #[case("isEmpty([], [])")]
+    #[case("isEmpty(0)")]
+    #[case("median([])")]
+    #[case("median([1, 'a'])")]
+    #[case("median(1)")]
+    #[case("stddev([])")]
+    #[case("stddev([1, true])")]
+    #[case("round()")]
+    #[case("round('a')")]
+    #[case("round(1.5, 1.5)")]
+    #[case("round(1.5, -1)")]
+    #[case("round(1.5, 1, 2)")]
+    #[case("round(1.5, 1, 'up')")]
+    #[case("round(1.5, 1, 'half_up', 2)")]
+    #[case("parseNumber()")]
+    #[case("parseNumber(12)")]
+    #[case("parseNumber('abc')")]
+    #[case("parseNumber('1.2.3')")]
+    #[case("parseNumber('1,5', ',', ',')")]
+    #[case("parseNumber('1,5', ',', '.', '-')")]
+    #[case("hasKey([1, 2], 1)")]
+    #[case("hasKey({'a': 1})")]
+    #[case("getOrDefault({'a': 1}, 'a')")]
+    #[case("getOrDefault([1, 2], 0, 1)")]
+    #[case("hasValue({'a': 1}, 1, 2)")]
+    #[case("type()")]
+    #[case("type(1, 2)")]
+    #[case("flatMap([1], 'missing')")]
+    #[case("flatMap(1, 'flat_dup')")]
+    #[case("flatMap([1])")]
+    #[case("map([1], 1)")]
+    #[case("map(1, flat_inc)")]
+    #[case("map([1], unknownFn)")]
+    #[case("filter([1], flat_inc)")]
+    #[case("currency(1, '$')")]
+    #[case("currency('a', '$', 2)")]
+    #[case("currency(1, '$', -1)")]
+    #[case("currency(1, '$', 1.5)")]
+    #[case("split('a,b')")]
+    #[case("split(1, ',')")]
+    #[case("split('a,b', ',', 1)")]
+    #[case("join(['a'])")]
+    #[case("join('ab', '-')")]
+    #[case("join([[1]], '-')")]
+    #[case("join(['a'], 1)")]
+    #[case("indexOf('abc')")]
+    #[case("indexOf('abc', 1)")]
+    #[case("indexOf(12, 1)")]
+    #[case("slice('abc')")]
+    #[case("slice('abc', 1.5)")]
+    #[case("slice('abc', 0, 'x')")]
+    #[case("slice(123, 0, 1)")]
+    #[case("slice('abc', 0, 1, 2)")]
+    #[case("replace('aaa', 'a')")]
+    #[case("replace(1, 'a', 'b')")]
+    #[case("replaceFirst('aaa', 'a', 1)")]
+    fn test_exec_error(#[case] input: &str) {
+        assert!(execute(input, Context::new()).is_err());
+    }
+
+    #[rstest]
+    #[case("stddev([79228162514264337593543950335, -79228162514264337593543950335])")]
+    #[case("stddev([79228162514264337593543950335, 79228162514264337593543950335])")]
+    fn test_exec_stddev_overflow(#[case] input: &str) {
+        assert!(matches!(
+            execute(input, Context::new()),
+            Err(Error::Overflow(op)) if op == "stddev"
+        ));
+    }
+
+    #[rstest]
+    #[case("map([1, 2], double)", Value::List(vec![2.into(), 4.into()]))]
+    #[case("sortByKeys([2, 1], [double])", Value::List(vec![1.into(), 2.into()]))]
+    #[case("double", 0.into())]
+    fn test_exec_context_function(#[case] input: &str, #[case] output: Value) {
+        let mut ctx = Context::new();
+        ctx.set_func(
+            "double",
+            Arc::new(|params| match params.first() {
+                Some(n) => Ok(Value::from(n.clone().decimal()? * Decimal::TWO)),
+                None => Ok(Value::from(0)),
+            }),
+        );
+        assert_eq!(execute(input, ctx).unwrap(), output);
+    }
+
+    #[rstest]
+    #[case("sortBy([3, 1, 2], sort_id)", Some(Value::List(vec![1.into(), 2.into(), 3.into()])))]
+    #[case("sortBy(['b', 'c', 'a'], 'sort_id')", Some(Value::List(vec!["a".into(), "b".into(), "c".into()])))]
+    #[case("sortBy([], sort_id)", Some(Value::List(vec![])))]
+    #[case(
+        "map(sortBy(people, sort_age), sort_name)",
+        Some(Value::List(vec!["di".into(), "bo".into(), "al".into(), "cy".into()]))
+    )]
+    #[case(
+        "map(sortByKeys(people, [sort_dept, sort_age]), sort_name)",
+        Some(Value::List(vec!["di".into(), "bo".into(), "al".into(), "cy".into()]))
+    )]
+    #[case(
+        "map(sortByKeys(people, [sort_dept]), sort_name)",
+        Some(Value::List(vec!["bo".into(), "di".into(), "al".into(), "cy".into()]))
+    )]
+    #[case("sortBy([1, 'a'], sort_id)", None)]
+    #[case("sortBy([true, false], sort_id)", None)]
+    #[case("sortBy([1, 2], 1)", None)]
+    #[case("sortBy({}, sort_id)", None)]
+    #[case("sortByKeys([1, 2], sort_id)", None)]
+    #[case("sortByKeys([1, 2], [sort_id, 1])", None)]
+    fn test_sort_by(#[case] input: &str, #[case] output: Option<Value>) {
+        register_function("sort_id", Arc::new(|params| Ok(params[0].clone())));
+        for field in ["name", "dept", "age"] {
+            register_function(
+                &format!("sort_{}", field),
+                Arc::new(move |params| {
+                    let m = params[0].clone().map()?;
+                    let found = m.into_iter().find(|(k, _)| *k == Value::from(field));
+                    Ok(found.map_or(Value::None, |(_, v)| v))
+                }),
+            );
+        }
+        let person = |name: &str, dept: &str, age: i32| {
+            Value::Map(vec![
+                ("name".into(), name.into()),
+                ("dept".into(), dept.into()),
+                ("age".into(), age.into()),
+            ])
+        };
+        let mut ctx = Context::new();
+        ctx.set_variable(
+            "people",
+            Value::from(vec![
+                person("al", "ops", 30),
+                person("bo", "dev", 25),
+                person("cy", "ops", 41),
+                person("di", "dev", 22),
+            ]),
+        );
+        let ans = execute(input, ctx);
+        match output {
+            Some(output) => assert_eq!(ans.unwrap(), output),
+            None => assert!(ans.is_err()),
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[rstest]
+    #[case("parseDate('2024-01-01')", Value::DateTime(1704067200000))]
+    #[case("parseDate('2024-01-01T08:30:00')", Value::DateTime(1704097800000))]
+    #[case(
+        "parseDate('2024-01-01T08:30:00+08:00')",
+        Value::DateTime(1704069000000)
+    )]
+    #[case("parseDate('01/02/2024', '%d/%m/%Y')", Value::DateTime(1706745600000))]
+    #[case(
+        "parseDate('2024-01-01 08:30', '%Y-%m-%d %H:%M')",
+        Value::DateTime(1704097800000)
+    )]
+    #[case("parseDate('2024-03-01') > parseDate('2024-01-01')", true.into())]
+    #[case("parseDate('2024-01-01') <= parseDate('2023-12-31T23:59:59')", false.into())]
+    #[case("parseDate('2024-01-01') == parseDate('2024-01-01T00:00:00Z')", true.into())]
+    #[case("now() > parseDate('2024-01-01')", true.into())]
+    #[case("type(now())", "datetime".into())]
+    #[case("days(2) + hours(12)", Value::Duration(216000))]
+    #[case("minutes(1.5) - seconds(30)", Value::Duration(60))]
+    #[case("days(0.5) == hours(12)", true.into())]
+    #[case("hours(1) > minutes(59)", true.into())]
+    #[case("parseDate('2024-02-28') + days(2)", Value::DateTime(1709251200000))]
+    #[case("hours(6) + parseDate('2024-01-01')", Value::DateTime(1704088800000))]
+    #[case("parseDate('2024-01-01') - minutes(1) < parseDate('2024-01-01')", true.into())]
+    #[case(
+        "parseDate('2024-01-02T12:00:00') - parseDate('2024-01-01')",
+        Value::Duration(129600)
+    )]
+    #[case("type(seconds(1))", "duration".into())]
+    fn test_exec_date(#[case] input: &str, #[case] output: Value) {
+        assert_eq!(execute(input, Context::new()).unwrap(), output);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[rstest]
+    #[case("parseDate('2024-13-01')")]
+    #[case("parseDate('2024-01-01', '%d/%m/%Y')")]
+    #[case("parseDate(20240101)")]
+    #[case("parseDate()")]
+    #[case("now(1)")]
+    #[case("parseDate('2024-01-01') > '2023-01-01'")]
+    #[case("seconds(1.5)")]
+    #[case("days(1000000000000000000000000)")]
+    #[case("seconds(10000000000000000000)")]
+    #[case("days('1')")]
+    #[case("days(1) + 1")]
+    #[case("days(1) - parseDate('2024-01-01')")]
+    #[case("parseDate('2024-01-01') + parseDate('2024-01-01')")]
+    #[case("days(1) * 2")]
+    fn test_exec_date_error(#[case] input: &str) {
+        assert!(execute(input, Context::new()).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_exec_duration_overflow() {
+        assert!(matches!(
+            execute("days(1000000000000000000000000)", Context::new()),
+            Err(Error::Overflow(op)) if op == "days"
+        ));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[rstest]
+    #[case(r#"parseJson('{"a":1,"b":[2,3]}')"#, Value::Map(vec![
+        ("a".into(), 1.into()),
+        ("b".into(), Value::List(vec![2.into(), 3.into()])),
+    ]))]
+    #[case(r#"hasKey(parseJson('{"a":1,"b":[2,3]}'), 'b')"#, true.into())]
+    #[case(r#"hasValue(parseJson('{"a":1,"b":[2,3]}'), [2, 3])"#, true.into())]
+    #[case("parseJson('[1.5, \"x\", true, null]')", Value::List(vec![
+        1.5.into(), "x".into(), true.into(), Value::None,
+    ]))]
+    #[case("parseJson(' 42 ')", 42.into())]
+    fn test_exec_json(#[case] input: &str, #[case] output: Value) {
+        assert_eq!(execute(input, Context::new()).unwrap(), output);
+    }
+
+    #[rstest]
+    #[case("isNumber(1.5)", true.into())]
+    #[case("isNumber('1.5')", false.into())]
+    #[case("isString('a')", true.into())]
+    #[case("isString(None)", false.into())]
+    #[case("isBool(false)", true.into())]
+    #[case("isBool(0)", false.into())]
+    #[case("isList([])", true.into())]
+    #[case("isList({})", false.into())]
+    #[case("isMap({'a': 1})", true.into())]
+    #[case("isMap([1])", false.into())]
+    #[case("assertType(2, 'number') * 3", 6.into())]
+    #[case("assertType('a', 'string')", "a".into())]
+    #[case("assertType(None, 'none')", Value::None)]
+    fn test_exec_type_checks(#[case] input: &str, #[case] output: Value) {
+        assert_eq!(execute(input, Context::new()).unwrap(), output);
+    }
+
+    #[rstest]
+    #[case("assertType('1', 'number')", "number", "string")]
+    #[case("assertType([1], 'map')", "map", "list")]
+    #[case("assertType(1, 'integer')", "integer", "number")]
+    fn test_exec_assert_type_error(
+        #[case] input: &str,
+        #[case] expected: &str,
+        #[case] actual: &str,
+    ) {
+        let err = execute(input, Context::new()).unwrap_err();
+        assert!(matches!(err, Error::UnexpectedType(e, a) if e == expected && a == actual));
+    }
+
+    #[rstest]
+    #[case("default(x, 0)", 5.into())]
+    #[case("default(missing, 0)", 0.into())]
+    #[case("default(missing, None)", Value::None)]
+    #[case("default(missing)", Value::None)]
+    #[case("default(missing, other, x, 1)", 5.into())]
+    #[case("default(None, false, true)", false.into())]
+    #[case("default(missing, 2) * 3 + 1", 7.into())]
+    fn test_exec_default(#[case] input: &str, #[case] output: Value) {
+        let ctx = Context::from_variables([("x".to_string(), Value::from(5))]);
+        assert_eq!(execute(input, ctx).unwrap(), output);
+    }
+
+    #[test]
+    fn test_exec_default_error() {
+        assert!(execute("default()", Context::new()).is_err());
+    }
+
+    #[rstest]
+    #[case("concat()", "")]
+    #[case("concat('a')", "a")]
+    #[case("concat('total: ', 1.50, ', paid: ', true)", "total: 1.50, paid: true")]
+    #[case("concat(1, 2, 3)", "123")]
+    #[case("concat(-2.5, false, '')", "-2.5false")]
+    fn test_exec_concat(#[case] input: &str, #[case] output: &str) {
+        assert_eq!(execute(input, Context::new()).unwrap(), output.into());
+    }
+
+    #[rstest]
+    #[case("concat('a', [1])")]
+    #[case("concat({'a':1})")]
+    #[case("concat('a', None)")]
+    fn test_exec_concat_error(#[case] input: &str) {
+        assert!(execute(input, Context::new()).is_err());
+    }
+
+    #[rstest]
+    #[case("format('{} is over {}', 'speed', 120)", "speed is over 120")]
+    #[case("format('no placeholders')", "no placeholders")]
+    #[case("format('{{}} and {{{}}}', 1.50)", "{} and {1.50}")]
+    #[case("format('{}, {}, {}', true, nil, max)", "true, nil, function")]
+    #[case("format('{}', [1, 'a', [nil]])", "[1, 'a', [nil]]")]
+    #[case("format('{}', {'a': 1, 2: 'b'})", "{'a': 1, 2: 'b'}")]
+    fn test_exec_format(#[case] input: &str, #[case] output: &str) {
+        assert_eq!(execute(input, Context::new()).unwrap(), output.into());
+    }
+
+    #[rstest]
+    #[case("format()")]
+    #[case("format(1)")]
+    #[case("format('{} and {}', 1)")]
+    #[case("format('{}', 1, 2)")]
+    #[case("format('{', 1)")]
+    #[case("format('a } b')")]
+    fn test_exec_format_error(#[case] input: &str) {
+        assert!(execute(input, Context::new()).is_err());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[rstest]
+    #[case("toJson({'a':1})", r#"{"a":1}"#)]
+    #[case("toJson([1, 'x', true, None])", r#"[1,"x",true,null]"#)]
+    #[case("toJson({'a':{'b':[1.5]}})", r#"{"a":{"b":[1.5]}}"#)]
+    #[case("toJson('x')", r#""x""#)]
+    #[case(r#"toJson(parseJson('{"a":[2,3]}'))"#, r#"{"a":[2,3]}"#)]
+    fn test_exec_to_json(#[case] input: &str, #[case] output: &str) {
+        assert_eq!(execute(input, Context::new()).unwrap(), output.into());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[rstest]
+    #[case("toJson({1:'a'})")]
+    #[case("toJson([{'a':{true:1}}])")]
+    #[case("toJson()")]
+    #[case("parseJson('{a:1}')")]
+    #[case("parseJson('')")]
+    #[case("parseJson(1)")]
+    #[case("parseJson('1', '2')")]
+    #[case("parseJson('1e30')")]
+    #[case(r#"parseJson('{"a":[1e40]}')"#)]
+    fn test_exec_json_error(#[case] input: &str) {
+        assert!(execute(input, Context::new()).is_err());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_exec_json_invalid() {
+        let err = execute("parseJson('{a:1}')", Context::new()).unwrap_err();
+        assert!(matches!(&err, Error::Custom(message) if message.starts_with("invalid json: ")));
+    }
 }
@@ -20,9 +20,13 @@ mod token;
 mod tokenizer;
 #[macro_use]
 mod value;
+mod analyzer;
+mod compiled;
 mod context;
+mod decorator;
 mod descriptor;
 mod init;
+mod registry;
 use std::sync::Arc;
 
 /// ## Usage
@@ -41,9 +45,108 @@ use std::sync::Arc;
 /// assert_eq!(ans, Value::from(21))
 /// ```
 pub fn execute(expr: &str, mut ctx: context::Context) -> Result<Value> {
+    ctx.reset_steps();
     parse_expression(expr)?.exec(&mut ctx)
 }
 
+/// ## Usage
+///
+/// You can attach the failing expression to evaluation errors via this method, which helps to
+/// tell rules apart in logs. Long expressions are truncated. Parse errors are returned as they are,
+/// and `execute`, `execute_ast`, `execute_over` and `CompiledExpression::eval` never wrap errors.
+///
+/// ``` rust
+/// use expression_engine::{execute_with_context, Context};
+/// let err = execute_with_context("1 < 2 && 'x'", Context::new()).unwrap_err();
+/// assert_eq!(err.to_string(), "should be bool in expression: 1 < 2 && 'x'");
+/// ```
+pub fn execute_with_context(expr: &str, mut ctx: context::Context) -> Result<Value> {
+    ctx.reset_steps();
+    parse_expression(expr)?
+        .exec(&mut ctx)
+        .map_err(|err| err.in_expression(expr))
+}
+
+/// ## Usage
+///
+/// You can convert the result to a Rust type via this method. Numbers convert to `i64`, `f64` and
+/// `Decimal`, and strings, bools and lists to `String`, `bool` and `Vec<Value>`.
+///
+/// ``` rust
+/// use expression_engine::{execute_as, Context};
+/// let n: i64 = execute_as("2 * 21", Context::new()).unwrap();
+/// assert_eq!(n, 42);
+/// assert!(execute_as::<bool>("'yes'", Context::new()).is_err());
+/// ```
+pub fn execute_as<T: TryFrom<Value, Error = Error>>(
+    expr: &str,
+    ctx: context::Context,
+) -> Result<T> {
+    execute(expr, ctx)?.try_into()
+}
+
+/// ## Usage
+///
+/// You can bound how long an untrusted expression runs via this method. Evaluation fails with
+/// `StepLimitExceeded` once it visits more than `max_steps` nodes.
+///
+/// ``` rust
+/// use expression_engine::{execute_with_limit, Context, Error, Value};
+/// assert_eq!(execute_with_limit("1 + 2", Context::new(), 10).unwrap(), Value::from(3));
+/// let err = execute_with_limit("1 + 2 + 3 + 4", Context::new(), 3).unwrap_err();
+/// assert!(matches!(err, Error::StepLimitExceeded(3)));
+/// ```
+pub fn execute_with_limit(
+    expr: &str,
+    mut ctx: context::Context,
+    max_steps: usize,
+) -> Result<Value> {
+    ctx.set_step_limit(Some(max_steps));
+    parse_expression(expr)?.exec(&mut ctx)
+}
+
+/// ## Usage
+///
+/// You can evaluate an expression against a JSON object via this method, which requires the
+/// `serde_json` feature. The fields of the object resolve as references, like a record set by
+/// `Context::set_record`, and JSON that isn't an object is an error.
+///
+/// ``` rust
+/// use expression_engine::{execute_json, Value};
+/// let ans = execute_json("age >= 18", r#"{"age": 21}"#).unwrap();
+/// assert_eq!(ans, Value::from(true));
+/// ```
+#[cfg(feature = "serde_json")]
+pub fn execute_json(expr: &str, json: &str) -> Result<Value> {
+    let mut ctx = Context::new();
+    ctx.set_record(Value::from_json_str(json)?)?;
+    execute(expr, ctx)
+}
+
+/// ## Usage
+///
+/// You can evaluate an expression parsed once via `parse_expression` against many contexts via this method.
+/// It also evaluates an `OwnedExprAST`, including one built in code.
+///
+/// ``` rust
+/// use expression_engine::{execute_ast, parse_expression, Context, Value};
+/// let ast = parse_expression("a * 2 + 1").unwrap();
+/// let mut ctx = Context::new();
+/// ctx.set_variable("a", Value::from(3));
+/// assert_eq!(execute_ast(&ast, &mut ctx).unwrap(), Value::from(7));
+/// let mut ctx = Context::new();
+/// ctx.set_variable("a", Value::from(10));
+/// assert_eq!(execute_ast(&ast, &mut ctx).unwrap(), Value::from(21));
+/// ```
+pub fn execute_ast<S: AsRef<str> + Clone>(
+    ast: &parser::Expr<S>,
+    ctx: &mut Context,
+) -> Result<Value> {
+    init();
+    ctx.reset_steps();
+    ast.exec(ctx)
+}
+
 /// ## Usage
 ///
 /// You can easily parse a string into ExprAST via this method.
@@ -59,6 +162,164 @@ pub fn parse_expression(expr: &str) -> Result<ExprAST> {
     parser::Parser::new(expr)?.parse_stmt()
 }
 
+/// ## Usage
+///
+/// You can parse a string into ExprAST with a custom nesting limit via this method.
+/// `parse_expression` and the other entry points allow a depth of 128, and deeper
+/// expressions fail with `Error::ExpressionTooDeep` instead of overflowing the stack.
+///
+/// ``` rust
+/// use expression_engine::{parse_expression_with_max_depth, Error};
+/// assert!(parse_expression_with_max_depth("-(1 + 2 + 3)", 4).is_ok());
+/// let input = "(".repeat(10) + "1" + &")".repeat(10);
+/// assert!(matches!(
+///     parse_expression_with_max_depth(&input, 8),
+///     Err(Error::ExpressionTooDeep(8))
+/// ));
+/// ```
+pub fn parse_expression_with_max_depth(expr: &str, max_depth: usize) -> Result<ExprAST<'_>> {
+    init();
+    let mut parser = parser::Parser::new(expr)?;
+    parser.set_max_depth(max_depth);
+    parser.parse_stmt()
+}
+
+/// ## Usage
+///
+/// You can parse a string into ExprAST along with the comments of each statement via this method. A statement
+/// gets the comments written before it or inside it, and the last one also gets the comments after it.
+///
+/// ``` rust
+/// use expression_engine::parse_expression_with_comments;
+/// let input = "# the base price\nprice = 10;\n# with tax\n# rounded\nround(price * 1.2)";
+/// let (ast, comments) = parse_expression_with_comments(input).unwrap();
+/// assert_eq!(comments, vec![vec!["the base price"], vec!["with tax", "rounded"]]);
+/// ```
+pub fn parse_expression_with_comments(expr: &str) -> Result<(ExprAST<'_>, Vec<Vec<&str>>)> {
+    init();
+    parser::Parser::with_comments(expr)?.parse_stmt_with_comments()
+}
+
+/// ## Usage
+///
+/// You can parse a single expression at the start of a string via this method, which also returns the
+/// byte offset where the text after it begins. This is useful for expressions embedded in a larger document.
+///
+/// ``` rust
+/// use expression_engine::parse_expression_with_end;
+/// let input = "1 + 2 rest";
+/// let (ast, end) = parse_expression_with_end(input).unwrap();
+/// assert_eq!(ast.expr(), "1 + 2");
+/// assert_eq!(&input[end..], "rest");
+/// ```
+pub fn parse_expression_with_end(expr: &str) -> Result<(ExprAST<'_>, usize)> {
+    init();
+    parser::Parser::new(expr)?.parse_expression_with_end()
+}
+
+/// ## Usage
+///
+/// You can parse a string into ExprAST which contains no assignments via this method,
+/// which is useful for pure predicates like filters.
+///
+/// ``` rust
+/// use expression_engine::{parse_pure_expression, Error};
+/// assert!(parse_pure_expression("a > 3").is_ok());
+/// assert!(matches!(
+///     parse_pure_expression("a = 3"),
+///     Err(Error::AssignmentNotAllowed)
+/// ));
+/// ```
+pub fn parse_pure_expression(expr: &str) -> Result<ExprAST<'_>> {
+    let ast = parse_expression(expr)?;
+    if ast.has_assignment() {
+        return Err(error::Error::AssignmentNotAllowed);
+    }
+    Ok(ast)
+}
+
+/// ## Usage
+///
+/// You can compile an expression once and evaluate it many times via this method,
+/// which avoids re-parsing when the same expression runs against different contexts.
+///
+/// ``` rust
+/// use expression_engine::{compile, Context, Value};
+/// let compiled = compile("a * 2 + 1").unwrap();
+/// for a in 1..4 {
+///     let mut ctx = Context::new();
+///     ctx.set_variable("a", Value::from(a));
+///     assert_eq!(compiled.eval(&mut ctx).unwrap(), Value::from(a * 2 + 1));
+/// }
+/// ```
+pub fn compile(expr: &str) -> Result<CompiledExpression> {
+    init();
+    compiled::CompiledExpression::new(expr)
+}
+
+/// ## Usage
+///
+/// You can evaluate an expression over many records via this method. The expression is parsed
+/// once, and each record map is evaluated in its own copy of the base context, so its fields
+/// resolve as references.
+///
+/// ``` rust
+/// use expression_engine::{execute_over, Context, Value};
+/// let records = vec![
+///     Value::Map(vec![(Value::from("price"), Value::from(2))]),
+///     Value::Map(vec![(Value::from("price"), Value::from(5))]),
+/// ];
+/// let mut base = Context::new();
+/// base.set_variable("qty", Value::from(3));
+/// let ans = execute_over("price * qty", &records, &base).unwrap();
+/// assert_eq!(ans, vec![Value::from(6), Value::from(15)]);
+/// ```
+pub fn execute_over(expr: &str, records: &[Value], base: &Context) -> Result<Vec<Value>> {
+    let compiled = compile(expr)?;
+    records
+        .iter()
+        .map(|record| {
+            let mut ctx = base.deep_clone();
+            ctx.set_record(record.clone())?;
+            compiled.eval(&mut ctx)
+        })
+        .collect()
+}
+
+/// ## Usage
+///
+/// You can check an expression for suspicious constructs via this method, such as
+/// comparisons whose result never changes, division by a literal zero and
+/// ternary branches that can't be reached.
+///
+/// ``` rust
+/// use expression_engine::analyze;
+/// let warnings = analyze("x == x || a / 0 > 1").unwrap();
+/// assert_eq!(warnings.len(), 2);
+/// assert_eq!(warnings[0].message, "comparison is always true");
+/// assert_eq!(warnings[1].expr, "a / 0");
+/// assert_eq!(warnings[1].span, 10..15);
+/// ```
+pub fn analyze(expr: &str) -> Result<Vec<Warning>> {
+    init();
+    let mut parser = parser::Parser::with_spans(expr)?;
+    let ast = parser.parse_stmt()?;
+    Ok(analyzer::analyze(&ast, &parser.take_spans()))
+}
+
+/// ## Usage
+///
+/// You can map a byte offset reported in an error back to its 1-based line and column via this method.
+///
+/// ``` rust
+/// use expression_engine::line_col;
+/// let input = "a = 1;\nb = a +;\nb";
+/// assert_eq!(line_col(input, 13), (2, 7));
+/// ```
+pub fn line_col(expr: &str, offset: usize) -> (usize, usize) {
+    tokenizer::Tokenizer::new(expr).line_col(offset)
+}
+
 /// ## Usage
 ///
 /// You can register some inner functions in advance via this method
@@ -130,6 +391,34 @@ pub fn register_postfix_op(op: &str, handler: Arc<operator::PostfixOpFunc>) {
     PostfixOpManager::new().register(op, handler);
 }
 
+/// ## Usage
+///
+/// You can overload a registered infix operator for operands of specific types via this method
+///
+/// ``` rust
+/// use std::sync::Arc;
+/// use expression_engine::{register_typed_infix_op, execute, Context, Value};
+/// register_typed_infix_op(
+///     "+",
+///     "string",
+///     "string",
+///     Arc::new(|left, right| Ok(Value::from(left.string()?.to_uppercase() + &right.string()?.to_uppercase()))),
+/// );
+/// assert_eq!(execute("'ab' + 'c'", Context::new()).unwrap(), Value::from("ABC"));
+/// assert_eq!(execute("1 + 2", Context::new()).unwrap(), Value::from(3));
+/// assert!(execute("'ab' + 1", Context::new()).is_err());
+/// ```
+pub fn register_typed_infix_op(
+    op: &str,
+    left_type: &'static str,
+    right_type: &'static str,
+    handler: Arc<operator::InfixOpFunc>,
+) {
+    use crate::operator::InfixOpManager;
+    init();
+    InfixOpManager::new().register_typed(op, left_type, right_type, handler);
+}
+
 /// ## Usage
 ///
 /// You can register some infix operators in advance via this method
@@ -161,6 +450,79 @@ pub fn register_infix_op(
     InfixOpManager::new().register(op, precedence, op_type, associativity, handler);
 }
 
+/// ## Usage
+///
+/// You can make a new infix operator behave exactly like a registered one via this method. It
+/// fails with `InfixOpNotRegistered` if the target operator isn't registered.
+///
+/// ``` rust
+/// use expression_engine::{redirect_infix_op, execute, Context, Value};
+/// redirect_infix_op("plus", "+").unwrap();
+/// assert_eq!(execute("1 plus 2 * 3", Context::new()).unwrap(), Value::from(7));
+/// assert!(redirect_infix_op("minus", "unknown").is_err());
+/// ```
+pub fn redirect_infix_op(source: &str, target: &str) -> Result<()> {
+    use crate::operator::InfixOpManager;
+    init();
+    InfixOpManager::new().redirect(source, target)
+}
+
+/// ## Usage
+///
+/// You can list the registered infix operators with their precedences via this method
+///
+/// ``` rust
+/// use expression_engine::registered_infix_ops;
+/// assert!(registered_infix_ops().contains(&("+".to_string(), 110)));
+/// ```
+pub fn registered_infix_ops() -> Vec<(String, i32)> {
+    use crate::operator::InfixOpManager;
+    init();
+    InfixOpManager::new().operators()
+}
+
+/// ## Usage
+///
+/// You can list the registered prefix operators via this method
+///
+/// ``` rust
+/// use expression_engine::registered_prefix_ops;
+/// assert!(registered_prefix_ops().contains(&"!".to_string()));
+/// ```
+pub fn registered_prefix_ops() -> Vec<String> {
+    use crate::operator::PrefixOpManager;
+    init();
+    PrefixOpManager::new().operators()
+}
+
+/// ## Usage
+///
+/// You can list the registered postfix operators via this method
+///
+/// ``` rust
+/// use expression_engine::registered_postfix_ops;
+/// assert!(registered_postfix_ops().contains(&"++".to_string()));
+/// ```
+pub fn registered_postfix_ops() -> Vec<String> {
+    use crate::operator::PostfixOpManager;
+    init();
+    PostfixOpManager::new().operators()
+}
+
+/// ## Usage
+///
+/// You can list the registered inner functions via this method
+///
+/// ``` rust
+/// use expression_engine::registered_functions;
+/// assert!(registered_functions().contains(&"min".to_string()));
+/// ```
+pub fn registered_functions() -> Vec<String> {
+    use crate::function::InnerFunctionManager;
+    init();
+    InnerFunctionManager::new().functions()
+}
+
 fn init() {
     use crate::init::init;
     init();
@@ -169,15 +531,37 @@ fn init() {
 pub type Value = value::Value;
 pub type Context = context::Context;
 pub type Result<T> = define::Result<T>;
+pub type Error = error::Error;
 pub type ExprAST<'a> = parser::ExprAST<'a>;
+/// An expression tree that owns its strings, built with its constructors or by
+/// `ExprAST::to_owned_ast`, and executed via `execute_ast`.
+///
+/// ``` rust
+/// use expression_engine::{execute_ast, Context, OwnedExprAST, Value};
+/// let ast = OwnedExprAST::binary("+", OwnedExprAST::reference("a"), OwnedExprAST::number(3));
+/// let mut ctx = Context::new();
+/// ctx.set_variable("a", Value::from(2));
+/// assert_eq!(execute_ast(&ast, &mut ctx).unwrap(), Value::from(5));
+/// assert_eq!(ast.expr(), "a + 3");
+/// ```
+pub type OwnedExprAST = parser::Expr<String>;
+pub type CompiledExpression = compiled::CompiledExpression;
+pub type Warning = analyzer::Warning;
 pub type InfixOpType = operator::InfixOpType;
 pub type InfixOpAssociativity = operator::InfixOpAssociativity;
+pub type DescriptorManager = descriptor::DescriptorManager;
+pub type DecoratorManager = decorator::DecoratorManager;
+pub type DecoratorKey = decorator::DecoratorKey;
+pub type BinaryOpMeta = descriptor::BinaryOpMeta;
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        create_context, execute, parse_expression, register_function, register_infix_op,
-        register_postfix_op, register_prefix_op, InfixOpAssociativity, InfixOpType, Value,
+        create_context, execute, execute_as, execute_ast, execute_over, execute_with_context,
+        execute_with_limit, parse_expression, parse_pure_expression, redirect_infix_op,
+        register_function, register_infix_op, register_postfix_op, register_prefix_op,
+        registered_functions, registered_infix_ops, registered_postfix_ops, registered_prefix_ops,
+        Context, Error, InfixOpAssociativity, InfixOpType, Value,
     };
     use std::sync::Arc;
     #[test]
@@ -192,12 +576,154 @@ mod tests {
         assert_eq!(ans, 21.into())
     }
 
+    #[test]
+    fn test_execute_as() {
+        let n: i64 = execute_as("1 + 2", Context::new()).unwrap();
+        assert_eq!(n, 3);
+        let b: bool = execute_as("1 < 2 && true", Context::new()).unwrap();
+        assert!(b);
+        let s: String = execute_as("concat('a', 'b')", Context::new()).unwrap();
+        assert_eq!(s, "ab");
+        assert!(matches!(
+            execute_as::<i64>("7 / 2", Context::new()),
+            Err(Error::NotInteger(_))
+        ));
+        assert!(matches!(
+            execute_as::<String>("1", Context::new()),
+            Err(Error::ShouldBeString())
+        ));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_execute_json() {
+        use crate::execute_json;
+        assert_eq!(
+            execute_json("age >= 18", r#"{"age": 21}"#).unwrap(),
+            Value::from(true)
+        );
+        assert_eq!(
+            execute_json(
+                "concat(user['tags'][1], ':', count)",
+                r#"{"user": {"tags": ["a", "b"]}, "count": "2"}"#
+            )
+            .unwrap(),
+            Value::from("b:2")
+        );
+        assert!(execute_json("age", "[1, 2]").is_err());
+        assert!(execute_json("age", "{").is_err());
+    }
+
+    #[test]
+    fn test_execute_with_limit() {
+        let chain = vec!["a += 1"; 10000].join("; ");
+        let err = execute_with_limit(
+            &chain,
+            Context::from_variables([("a".to_string(), Value::from(0))]),
+            1000,
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::StepLimitExceeded(1000)));
+        let ans = execute_with_limit("a = 1; a + [1, 2][0]", Context::new(), 1000).unwrap();
+        assert_eq!(ans, Value::from(2));
+        let ast = parse_expression("1 + 2 + 3").unwrap();
+        let mut ctx = Context::new();
+        ctx.set_step_limit(Some(10));
+        for _ in 0..5 {
+            assert_eq!(execute_ast(&ast, &mut ctx).unwrap(), Value::from(6));
+        }
+    }
+
+    #[test]
+    fn test_execute_error_context() {
+        let err = execute_with_context(
+            "a + 1 > 2 && 'x'",
+            Context::from_variables([("a".to_string(), Value::from(3))]),
+        )
+        .unwrap_err();
+        assert!(matches!(&err, Error::EvalContext(expr, inner)
+            if expr == "a + 1 > 2 && 'x'" && matches!(**inner, Error::ShouldBeBool())));
+        assert_eq!(
+            err.to_string(),
+            "should be bool in expression: a + 1 > 2 && 'x'"
+        );
+        let input = format!("{} + 'x'", vec!["1"; 40].join(" + "));
+        let err = execute_with_context(&input, Context::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "invalid operands for +: number and string in expression: {}...",
+                &input[..64]
+            )
+        );
+        assert!(matches!(
+            execute_with_context("1 +", Context::new()),
+            Err(Error::UnexpectedEOF(_))
+        ));
+        assert!(matches!(
+            execute("1 < 2 && 'x'", Context::new()),
+            Err(Error::ShouldBeBool())
+        ));
+    }
+
+    #[test]
+    fn test_divide_by_zero() {
+        for input in ["1/0", "1 % 0", "d /= 0", "d %= 0", "d = 0; 2 / d"] {
+            let ctx = Context::from_variables([("d".to_string(), Value::from(4))]);
+            let err = execute(input, ctx).unwrap_err();
+            assert!(matches!(err, Error::DivideByZero), "{}", input);
+        }
+        for (input, output) in [
+            ("1/4", 0.25),
+            ("7 % 4", 3.0),
+            ("d /= 2; d", 2.0),
+            ("d %= 3; d", 1.0),
+        ] {
+            let ctx = Context::from_variables([("d".to_string(), Value::from(4))]);
+            assert_eq!(execute(input, ctx).unwrap(), Value::from(output));
+        }
+    }
+
+    #[test]
+    fn test_execute_over() {
+        let records: Vec<Value> = [(1, 2), (3, 4), (5, 6)]
+            .into_iter()
+            .map(|(a, b)| {
+                Value::Map(vec![
+                    (Value::from("a"), Value::from(a)),
+                    (Value::from("b"), Value::from(b)),
+                ])
+            })
+            .collect();
+        let mut base = Context::new();
+        base.set_variable("c", Value::from(1));
+        let ans = execute_over("a + b", &records, &base).unwrap();
+        assert_eq!(ans, vec![3.into(), 7.into(), 11.into()]);
+        let ans = execute_over("c += a; c", &records, &base).unwrap();
+        assert_eq!(ans, vec![2.into(), 4.into(), 6.into()]);
+        assert_eq!(base.value("c").unwrap(), 1.into());
+        assert!(execute_over("a", &[Value::from(1)], &base).is_err());
+    }
+
     #[test]
     fn test_parse_expression() {
         let input = "a + 3*2+test()+[1,2,3,'haha']";
         assert!(parse_expression(input).is_ok());
     }
 
+    #[test]
+    fn test_parse_pure_expression() {
+        assert!(parse_pure_expression("a > 3").is_ok());
+        assert!(matches!(
+            parse_pure_expression("a = 3"),
+            Err(Error::AssignmentNotAllowed)
+        ));
+        assert!(matches!(
+            parse_pure_expression("[1, b += 2]"),
+            Err(Error::AssignmentNotAllowed)
+        ));
+    }
+
     #[test]
     fn test_register_function() {
         register_function("test", Arc::new(|_| return Ok(Value::from("test"))));
@@ -257,4 +783,110 @@ mod tests {
         assert!(ans.is_ok());
         assert_eq!(ans.unwrap(), Value::from(89));
     }
+
+    #[test]
+    fn test_registered_ops_and_functions() {
+        let infix_ops = registered_infix_ops();
+        for op in ["+", "&&", "==", "beginWith", "??"] {
+            assert!(infix_ops.iter().any(|(name, _)| name == op), "{}", op);
+        }
+        assert!(infix_ops.windows(2).all(|w| w[0].1 <= w[1].1));
+        let prefix_ops = registered_prefix_ops();
+        for op in ["-", "!", "not", "~"] {
+            assert!(prefix_ops.contains(&op.to_string()), "{}", op);
+        }
+        let postfix_ops = registered_postfix_ops();
+        for op in ["++", "--"] {
+            assert!(postfix_ops.contains(&op.to_string()), "{}", op);
+        }
+        let functions = registered_functions();
+        for name in ["min", "max", "sum", "mul", "currency"] {
+            assert!(functions.contains(&name.to_string()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_register_infix_op_sharing_prefix() {
+        register_infix_op(
+            "=>",
+            45,
+            InfixOpType::CALC,
+            InfixOpAssociativity::RIGHT,
+            Arc::new(|left, right| Ok(Value::from(!left.bool()? || right.bool()?))),
+        );
+        for (input, output) in [
+            ("false => false", true),
+            ("true => false", false),
+            ("a = 1; a == 1 => a >= 1", true),
+            ("1 == 2 || true => false", false),
+        ] {
+            assert_eq!(execute(input, Context::new()).unwrap(), Value::from(output));
+        }
+    }
+
+    #[test]
+    fn test_register_prefix_and_infix_op() {
+        register_prefix_op("minus", Arc::new(|v| Ok(Value::from(-v.decimal()?))));
+        register_infix_op(
+            "minus",
+            110,
+            InfixOpType::CALC,
+            InfixOpAssociativity::LEFT,
+            Arc::new(|left, right| Ok(Value::from(left.decimal()? - right.decimal()?))),
+        );
+        for (input, output) in [
+            ("minus 5 minus minus 3", -2),
+            ("2 minus 1", 1),
+            ("minus 2 * 3 minus 1", -7),
+        ] {
+            assert_eq!(execute(input, Context::new()).unwrap(), Value::from(output));
+        }
+    }
+
+    #[test]
+    fn test_redirect_infix_op() {
+        redirect_infix_op("redirected_and", "&&").unwrap();
+        redirect_infix_op("redirected_times", "*").unwrap();
+        for (input, output) in [
+            ("true redirected_and false", Value::from(false)),
+            ("1 + 2 redirected_times 3", Value::from(7)),
+        ] {
+            assert_eq!(execute(input, Context::new()).unwrap(), output);
+        }
+        assert!(matches!(
+            redirect_infix_op("redirected_missing", "missing"),
+            Err(Error::InfixOpNotRegistered(op)) if op == "missing"
+        ));
+        let ops = registered_infix_ops();
+        assert!(!ops.iter().any(|(op, _)| op == "redirected_missing"));
+        let precedence = |name: &str| ops.iter().find(|(op, _)| op == name).map(|(_, p)| *p);
+        assert_eq!(precedence("redirected_times"), precedence("*"));
+    }
+
+    #[test]
+    fn test_register_word_prefix_op() {
+        use rust_decimal::MathematicalOps;
+        register_prefix_op(
+            "sqrt_of",
+            Arc::new(|v| {
+                v.decimal()?
+                    .sqrt()
+                    .map(Value::from)
+                    .ok_or(Error::ParamInvalid())
+            }),
+        );
+        for (input, output) in [
+            ("sqrt_of 9", Value::from(3)),
+            ("sqrt_of(16) + 1", Value::from(5)),
+            ("sqrt_of+9", Value::from(3)),
+            ("x = 81; sqrt_of sqrt_of x", Value::from(3)),
+            (
+                "[sqrt_of 4, 1]",
+                Value::from(vec![Value::from(2), Value::from(1)]),
+            ),
+            ("sqrt_of_x = 4; sqrt_of sqrt_of_x", Value::from(2)),
+        ] {
+            assert_eq!(execute(input, Context::new()).unwrap(), output);
+        }
+    }
 }
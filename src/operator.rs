@@ -2,10 +2,40 @@ use crate::define::Result;
 use crate::error::Error;
 use crate::value::Value;
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 use rust_decimal::prelude::FromPrimitive;
 use rust_decimal::Decimal;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// The length in bytes of the longest operator registered so far, which bounds
+// how far the tokenizer looks ahead when matching operators.
+static MAX_OP_LEN: AtomicUsize = AtomicUsize::new(1);
+
+pub fn max_op_len() -> usize {
+    MAX_OP_LEN.load(Ordering::Relaxed)
+}
+
+// Set once any typed overload is registered, so that evaluation can skip the
+// overload lookup entirely in the common case.
+static HAS_TYPED: AtomicBool = AtomicBool::new(false);
+
+// Maps a failed checked division to its cause: a zero divisor, or else a
+// result out of the `Decimal` range.
+fn checked_result(op: &str, ans: Option<Decimal>, divisor: Decimal) -> Result<Decimal> {
+    ans.ok_or_else(|| {
+        if divisor.is_zero() {
+            Error::DivideByZero
+        } else {
+            Error::Overflow(op.to_string())
+        }
+    })
+}
+
+fn record_op_len(op: &str) {
+    MAX_OP_LEN.fetch_max(op.len(), Ordering::Relaxed);
+}
 
 pub type InfixOpFunc = dyn Fn(Value, Value) -> Result<Value> + Send + Sync + 'static;
 
@@ -33,8 +63,12 @@ pub struct InfixOpConfig(
     pub Arc<InfixOpFunc>,
 );
 
+// Handlers registered for an operator, keyed by the pair of operand type names.
+type TypedInfixOpStore = HashMap<String, HashMap<(&'static str, &'static str), Arc<InfixOpFunc>>>;
+
 pub struct InfixOpManager {
     store: &'static Mutex<HashMap<String, InfixOpConfig>>,
+    typed: &'static Mutex<TypedInfixOpStore>,
 }
 
 pub struct PrefixOpManager {
@@ -48,8 +82,10 @@ pub struct PostfixOpManager {
 impl InfixOpManager {
     pub fn new() -> Self {
         static STORE: OnceCell<Mutex<HashMap<String, InfixOpConfig>>> = OnceCell::new();
+        static TYPED: OnceCell<Mutex<TypedInfixOpStore>> = OnceCell::new();
         let store = STORE.get_or_init(|| Mutex::new(HashMap::new()));
-        InfixOpManager { store: store }
+        let typed = TYPED.get_or_init(|| Mutex::new(HashMap::new()));
+        InfixOpManager { store, typed }
     }
 
     pub fn init(&mut self) {
@@ -65,6 +101,9 @@ impl InfixOpManager {
                 RIGHT,
                 Arc::new(move |left, right| {
                     let (mut a, b) = (left.decimal()?, right.decimal()?);
+                    if matches!(op, "/=" | "%=") && b.is_zero() {
+                        return Err(Error::DivideByZero);
+                    }
                     match op {
                         "+=" => a += b,
                         "-=" => a -= b,
@@ -99,6 +138,28 @@ impl InfixOpManager {
             );
         }
 
+        self.register(
+            "??=",
+            20,
+            SETTER,
+            RIGHT,
+            Arc::new(|left, right| match left {
+                Value::None => Ok(right),
+                _ => Ok(left),
+            }),
+        );
+
+        self.register(
+            "??",
+            30,
+            CALC,
+            RIGHT,
+            Arc::new(|left, right| match left {
+                Value::None => Ok(right),
+                _ => Ok(left),
+            }),
+        );
+
         for (op, precedence) in vec![("||", 40), ("&&", 50)] {
             self.register(
                 op,
@@ -124,16 +185,24 @@ impl InfixOpManager {
                 CALC,
                 LEFT,
                 Arc::new(move |left, right| {
-                    let (a, b) = (left.decimal()?, right.decimal()?);
-                    let mut value = false;
+                    // Strings and other values that `Value::lt` and friends can
+                    // order aren't accepted by the operators.
+                    let ordered = match (&left, &right) {
+                        (Value::Number(_), Value::Number(_)) => true,
+                        #[cfg(feature = "chrono")]
+                        (Value::DateTime(_), Value::DateTime(_))
+                        | (Value::Duration(_), Value::Duration(_)) => true,
+                        _ => false,
+                    };
+                    if !ordered {
+                        return Err(Error::ShouldBeNumber());
+                    }
                     match op {
-                        "<" => value = a < b,
-                        "<=" => value = a <= b,
-                        ">" => value = a > b,
-                        ">=" => value = a >= b,
-                        _ => (),
+                        "<" => left.lt(&right),
+                        "<=" => left.le(&right),
+                        ">" => left.gt(&right),
+                        _ => left.ge(&right),
                     }
-                    Ok(Value::from(value))
                 }),
             );
         }
@@ -184,7 +253,23 @@ impl InfixOpManager {
                 CALC,
                 LEFT,
                 Arc::new(move |left, right| {
-                    let (mut a, b) = (left.decimal()?, right.decimal()?);
+                    let (mut a, b) = match (left, right) {
+                        (Value::Number(a), Value::Number(b)) => (a, b),
+                        (left, right) => {
+                            #[cfg(feature = "chrono")]
+                            if let Some(ans) = time_arithmetic(op, &left, &right) {
+                                return ans;
+                            }
+                            return Err(Error::InvalidOperands(
+                                op.to_string(),
+                                left.type_name().to_string(),
+                                right.type_name().to_string(),
+                            ));
+                        }
+                    };
+                    if matches!(op, "/" | "%") && b.is_zero() {
+                        return Err(Error::DivideByZero);
+                    }
                     match op {
                         "+" => a += b,
                         "-" => a -= b,
@@ -198,6 +283,33 @@ impl InfixOpManager {
             );
         }
 
+        self.register(
+            "//",
+            120,
+            CALC,
+            LEFT,
+            Arc::new(|left, right| {
+                let (a, b) = (left.decimal()?, right.decimal()?);
+                let q = checked_result("//", a.checked_div(b), b)?;
+                Ok(Value::from(q.floor()))
+            }),
+        );
+
+        self.register(
+            "mod",
+            120,
+            CALC,
+            LEFT,
+            Arc::new(|left, right| {
+                let (a, b) = (left.decimal()?, right.decimal()?);
+                let r = checked_result("mod", a.checked_rem(b), b)?;
+                if !r.is_zero() && r.is_sign_negative() != b.is_sign_negative() {
+                    return Ok(Value::from(r + b));
+                }
+                Ok(Value::from(r))
+            }),
+        );
+
         self.register(
             "beginWith",
             200,
@@ -220,6 +332,37 @@ impl InfixOpManager {
             }),
         );
 
+        for op in ["beginWithI", "endWithI"] {
+            self.register(
+                op,
+                200,
+                CALC,
+                LEFT,
+                Arc::new(move |left, right| {
+                    let (a, b) = (
+                        left.string()?.to_lowercase(),
+                        right.string()?.to_lowercase(),
+                    );
+                    match op {
+                        "beginWithI" => Ok(Value::from(a.starts_with(&b))),
+                        _ => Ok(Value::from(a.ends_with(&b))),
+                    }
+                }),
+            );
+        }
+
+        #[cfg(feature = "regex")]
+        self.register(
+            "matches",
+            200,
+            CALC,
+            LEFT,
+            Arc::new(|left, right| {
+                let (a, b) = (left.string()?, right.string()?);
+                Ok(Value::from(regex_is_match(&a, &b)?))
+            }),
+        );
+
         self.register(
             "in",
             200,
@@ -245,16 +388,70 @@ impl InfixOpManager {
         op_associativity: InfixOpAssociativity,
         f: Arc<InfixOpFunc>,
     ) {
-        self.store.lock().unwrap().insert(
+        record_op_len(op);
+        self.store.lock().insert(
             op.to_string(),
             InfixOpConfig(precidence, op_type, op_associativity, f),
         );
     }
 
+    /// Registers `source` as another spelling of `target`, with its handler,
+    /// precedence, associativity and typed overloads. Fails if `target` isn't
+    /// registered.
+    pub fn redirect(&mut self, source: &str, target: &str) -> Result<()> {
+        let config = self.get(target)?;
+        let typed = self.typed.lock().get(target).cloned();
+        if let Some(typed) = typed {
+            self.typed.lock().insert(source.to_string(), typed);
+        }
+        record_op_len(source);
+        self.store.lock().insert(source.to_string(), config);
+        Ok(())
+    }
+
     pub fn get_handler(&self, op: &str) -> Result<Arc<InfixOpFunc>> {
         Ok(self.get(op)?.3)
     }
 
+    /// Overloads a registered operator for operands of the given types, named as
+    /// by `Value::type_name`, e.g. `"string"`. Operands of other types still go to
+    /// the handler passed to `register`.
+    pub fn register_typed(
+        &mut self,
+        op: &str,
+        left_type: &'static str,
+        right_type: &'static str,
+        f: Arc<InfixOpFunc>,
+    ) {
+        self.typed
+            .lock()
+            .entry(op.to_string())
+            .or_default()
+            .insert((left_type, right_type), f);
+        HAS_TYPED.store(true, Ordering::Release);
+    }
+
+    /// Returns the handler overloaded for the types of the operands, or else the
+    /// operator's own handler.
+    pub fn get_typed_handler(
+        &self,
+        op: &str,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Arc<InfixOpFunc>> {
+        if HAS_TYPED.load(Ordering::Acquire) {
+            let typed = self
+                .typed
+                .lock()
+                .get(op)
+                .and_then(|m| m.get(&(left.type_name(), right.type_name())).cloned());
+            if let Some(f) = typed {
+                return Ok(f);
+            }
+        }
+        self.get_handler(op)
+    }
+
     pub fn get_precidence(&self, op: &str) -> (i32, i32) {
         let ans = self.get(op);
         if ans.is_err() {
@@ -276,7 +473,7 @@ impl InfixOpManager {
     }
 
     pub fn get(&self, op: &str) -> Result<InfixOpConfig> {
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         let ans = binding.get(op);
         if ans.is_none() {
             return Err(Error::InfixOpNotRegistered(op.to_string()));
@@ -286,16 +483,16 @@ impl InfixOpManager {
 
     pub fn operators(&self) -> Vec<(String, i32)> {
         let mut ans = vec![];
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         for (op, InfixOpConfig(precedence, _, _, _)) in binding.iter() {
-            ans.push((op.clone(), precedence.clone()));
+            ans.push((op.clone(), *precedence));
         }
-        ans.sort_by(|a, b| a.1.cmp(&b.1));
+        ans.sort_by_key(|a| a.1);
         ans
     }
 
     pub fn exist(&self, op: &str) -> bool {
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         binding.get(op).is_some()
     }
 }
@@ -304,7 +501,7 @@ impl PrefixOpManager {
     pub fn new() -> Self {
         static STORE: OnceCell<Mutex<HashMap<String, Arc<PrefixOpFunc>>>> = OnceCell::new();
         let store = STORE.get_or_init(|| Mutex::new(HashMap::new()));
-        PrefixOpManager { store: store }
+        PrefixOpManager { store }
     }
 
     pub fn init(&mut self) {
@@ -352,6 +549,14 @@ impl PrefixOpManager {
             }),
         );
 
+        self.register(
+            "~",
+            Arc::new(|param| {
+                let a = param.integer()?;
+                Ok(Value::from(!a))
+            }),
+        );
+
         self.register(
             "AND",
             Arc::new(|value| {
@@ -380,11 +585,12 @@ impl PrefixOpManager {
     }
 
     pub fn register(&mut self, op: &str, f: Arc<PrefixOpFunc>) {
-        self.store.lock().unwrap().insert(op.to_string(), f);
+        record_op_len(op);
+        self.store.lock().insert(op.to_string(), f);
     }
 
     pub fn get(&self, op: &str) -> Result<Arc<PrefixOpFunc>> {
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         let ans = binding.get(op);
         if ans.is_none() {
             return Err(Error::PrefixOpNotRegistered(op.to_string()));
@@ -392,8 +598,14 @@ impl PrefixOpManager {
         Ok(ans.unwrap().clone())
     }
 
+    pub fn operators(&self) -> Vec<String> {
+        let mut ans: Vec<String> = self.store.lock().keys().cloned().collect();
+        ans.sort();
+        ans
+    }
+
     pub fn exist(&self, op: &str) -> bool {
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         binding.get(op).is_some()
     }
 }
@@ -402,7 +614,7 @@ impl PostfixOpManager {
     pub fn new() -> Self {
         static STORE: OnceCell<Mutex<HashMap<String, Arc<PrefixOpFunc>>>> = OnceCell::new();
         let store = STORE.get_or_init(|| Mutex::new(HashMap::new()));
-        Self { store: store }
+        Self { store }
     }
 
     pub fn init(&mut self) {
@@ -430,11 +642,12 @@ impl PostfixOpManager {
     }
 
     pub fn register(&mut self, op: &str, f: Arc<PostfixOpFunc>) {
-        self.store.lock().unwrap().insert(op.to_string(), f);
+        record_op_len(op);
+        self.store.lock().insert(op.to_string(), f);
     }
 
     pub fn get(&self, op: &str) -> Result<Arc<PostfixOpFunc>> {
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         let ans = binding.get(op);
         if ans.is_none() {
             return Err(Error::PrefixOpNotRegistered(op.to_string()));
@@ -442,15 +655,84 @@ impl PostfixOpManager {
         Ok(ans.unwrap().clone())
     }
 
+    pub fn operators(&self) -> Vec<String> {
+        let mut ans: Vec<String> = self.store.lock().keys().cloned().collect();
+        ans.sort();
+        ans
+    }
+
     pub fn exist(&self, op: &str) -> bool {
-        let binding = self.store.lock().unwrap();
+        let binding = self.store.lock();
         binding.get(op).is_some()
     }
 }
 
+// Durations add to and subtract from dates and other durations, and the difference
+// of two dates is a duration. Returns None for any other combination.
+#[cfg(feature = "chrono")]
+fn time_arithmetic(op: &str, left: &Value, right: &Value) -> Option<Result<Value>> {
+    use Value::{DateTime, Duration};
+    let ans = match (op, left, right) {
+        ("+", DateTime(t), Duration(d)) | ("+", Duration(d), DateTime(t)) => d
+            .checked_mul(1000)
+            .and_then(|d| t.checked_add(d))
+            .map(DateTime),
+        ("-", DateTime(t), Duration(d)) => d
+            .checked_mul(1000)
+            .and_then(|d| t.checked_sub(d))
+            .map(DateTime),
+        ("-", DateTime(a), DateTime(b)) => a.checked_sub(*b).map(|d| Duration(d / 1000)),
+        ("+", Duration(a), Duration(b)) => a.checked_add(*b).map(Duration),
+        ("-", Duration(a), Duration(b)) => a.checked_sub(*b).map(Duration),
+        _ => return None,
+    };
+    Some(ans.ok_or_else(|| {
+        Error::IntegerOutOfRange(format!("{} {} {}", left.type_name(), op, right.type_name()))
+    }))
+}
+
+// The most patterns kept compiled. Patterns can come from variables, so the
+// cache is cleared once it's full instead of growing without bound.
+#[cfg(feature = "regex")]
+const REGEX_CACHE_SIZE: usize = 256;
+
+// Compiled patterns are cached, since rules usually match against a few constant
+// patterns. The match runs on a clone, so concurrent matches don't wait on the
+// cache lock.
+#[cfg(feature = "regex")]
+fn regex_is_match(s: &str, pattern: &str) -> Result<bool> {
+    static CACHE: OnceCell<Mutex<HashMap<String, regex::Regex>>> = OnceCell::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    let cached = cache.lock().get(pattern).cloned();
+    let re = match cached {
+        Some(re) => re,
+        None => {
+            let re = regex::Regex::new(pattern)
+                .map_err(|err| Error::InvalidRegex(pattern.to_string(), err.to_string()))?;
+            let mut cache = cache.lock();
+            if cache.len() >= REGEX_CACHE_SIZE {
+                cache.clear();
+            }
+            cache.insert(pattern.to_string(), re.clone());
+            re
+        }
+    };
+    Ok(re.is_match(s))
+}
+
 #[cfg(test)]
 mod tetst {
+    use super::{InfixOpAssociativity, InfixOpType};
+    use crate::context::Context;
+    use crate::error::Error;
+    use crate::execute;
+    use crate::init::init;
     use crate::operator::InfixOpManager;
+    use crate::parser::Parser;
+    use crate::value::Value;
+    use rstest::rstest;
+    use std::sync::Arc;
+
     #[test]
     fn test_operators() {
         let result = InfixOpManager::new().operators();
@@ -458,4 +740,117 @@ mod tetst {
             println!("|{}| {}||", op, precedence)
         }
     }
+
+    #[rstest]
+    #[case("1 // 0", Error::DivideByZero)]
+    #[case("1 mod 0", Error::DivideByZero)]
+    #[case(
+        "79228162514264337593543950335 // 0.1",
+        Error::Overflow("//".to_string())
+    )]
+    fn test_checked_division(#[case] input: &str, #[case] output: Error) {
+        let err = execute(input, Context::new()).unwrap_err();
+        assert_eq!(err.to_string(), output.to_string());
+    }
+
+    #[rstest]
+    #[case("'a' % [1]", "%", "string", "list")]
+    #[case("'a' % 1", "%", "string", "number")]
+    #[case("1 % 'a'", "%", "number", "string")]
+    #[case("[1] % 2", "%", "list", "number")]
+    #[case("{'a': 1} % {}", "%", "map", "map")]
+    #[case("true % false", "%", "bool", "bool")]
+    #[case("x % 2", "%", "none", "number")]
+    #[case("'a' + 'b'", "+", "string", "string")]
+    fn test_invalid_operands(
+        #[case] input: &str,
+        #[case] op: &str,
+        #[case] lhs: &str,
+        #[case] rhs: &str,
+    ) {
+        init();
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        let ans = ast.exec(&mut Context::new());
+        assert!(
+            matches!(ans, Err(Error::InvalidOperands(o, a, b)) if o == op && a == lhs && b == rhs)
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[rstest]
+    #[case(r"'555-1234' matches '^\d{3}-\d{4}$'", Some(true))]
+    #[case(r"'5551234' matches '^\d{3}-\d{4}$'", Some(false))]
+    #[case("'a@b.com' matches '@' && 'x' matches 'x'", Some(true))]
+    #[case("'abc' matches '(unclosed'", None)]
+    fn test_matches(#[case] input: &str, #[case] output: Option<bool>) {
+        init();
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        let ans = ast.exec(&mut Context::new());
+        match output {
+            Some(output) => assert_eq!(ans.unwrap(), Value::from(output)),
+            None => assert!(
+                matches!(ans, Err(Error::InvalidRegex(p, err)) if p == "(unclosed" && !err.is_empty())
+            ),
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_cache_is_bounded() {
+        for i in 0..super::REGEX_CACHE_SIZE * 2 {
+            let pattern = format!("^a{{{}}}$", i);
+            assert!(super::regex_is_match(&"a".repeat(i), &pattern).unwrap());
+            assert!(!super::regex_is_match("b", &pattern).unwrap());
+        }
+    }
+
+    #[rstest]
+    #[case("1 typed_merge 2", Some(3.into()))]
+    #[case("'ab' typed_merge 'c'", Some("ABC".into()))]
+    #[case("'ab' typed_merge 'c' typed_merge 'd'", Some("ABCD".into()))]
+    #[case("'ab' typed_merge 1", None)]
+    fn test_register_typed(#[case] input: &str, #[case] output: Option<Value>) {
+        init();
+        InfixOpManager::new().register(
+            "typed_merge",
+            110,
+            InfixOpType::CALC,
+            InfixOpAssociativity::LEFT,
+            Arc::new(|left, right| Ok(Value::from(left.decimal()? + right.decimal()?))),
+        );
+        InfixOpManager::new().register_typed(
+            "typed_merge",
+            "string",
+            "string",
+            Arc::new(|left, right| {
+                Ok(Value::from(
+                    left.string()?.to_uppercase() + &right.string()?.to_uppercase(),
+                ))
+            }),
+        );
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        let ans = ast.exec(&mut Context::new());
+        match output {
+            Some(output) => assert_eq!(ans.unwrap(), output),
+            None => assert!(ans.is_err()),
+        }
+    }
+
+    #[rstest]
+    #[case("{'a': 1, 'b': 2} == {'b': 2, 'a': 1}", true)]
+    #[case("{'a': 1, 'b': 2} != {'b': 2, 'a': 1}", false)]
+    #[case("{'a': 1, 'b': 2} == {'a': 1, 'b': 3}", false)]
+    #[case("{'b': 2, 'a': 1} in [{'a': 1}, {'a': 1, 'b': 2}]", true)]
+    #[case("{'a': [1, {'y': 1, 'x': 0}]} in [{'a': [1, {'x': 0, 'y': 1}]}]", true)]
+    #[case("{'a': [{'x': 0}, 1]} in [{'a': [1, {'x': 0}]}]", false)]
+    #[case("{'a': 1} in [{'a': 1, 'b': 2}]", false)]
+    #[case("{'a': 1, 'a': 1, 'b': 2} == {'a': 1, 'b': 2, 'b': 2}", false)]
+    #[case("{'a': 1, 'b': 2, 'a': 1} == {'a': 1, 'a': 1, 'b': 2}", true)]
+    #[case("{1: 'x', '1': 'y'} == {'1': 'y', 1: 'x'}", true)]
+    #[case("{'a': {'x': 1, 'y': [2]}} == {'a': {'y': [2], 'x': 1.0}}", true)]
+    fn test_map_equality(#[case] input: &str, #[case] output: bool) {
+        init();
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec(&mut Context::new()).unwrap(), Value::from(output));
+    }
 }
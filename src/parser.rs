@@ -1,87 +1,151 @@
-use crate::context::Context;
+use crate::context::{Context, ContextValue};
+use crate::decorator::{DecoratorKey, DecoratorManager};
 use crate::define::*;
-use crate::descriptor::DescriptorManager;
+use crate::descriptor::{BinaryOpMeta, DescriptorManager};
 use crate::error::Error;
 use crate::function::InnerFunctionManager;
+use crate::keyword;
 use crate::operator::{InfixOpManager, InfixOpType, PostfixOpManager, PrefixOpManager};
-use crate::token::{DelimTokenType, Token};
+use crate::token::{DelimTokenType, Span, Token};
 use crate::tokenizer::Tokenizer;
 use crate::value::Value;
 use rust_decimal::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
 
-#[derive(Clone, PartialEq, Eq, Debug)]
-pub enum Literal<'a> {
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Literal<S> {
     Number(Decimal),
     Bool(bool),
-    String(&'a str),
+    String(S),
 }
 
 #[cfg(not(tarpaulin_include))]
-impl<'a> fmt::Display for Literal<'a> {
+impl<S: AsRef<str>> fmt::Display for Literal<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use Literal::*;
         match self {
-            Number(value) => write!(f, "Number: {}", value.clone()),
-            Bool(value) => write!(f, "Bool: {}", value.clone()),
-            String(value) => write!(f, "String: {}", *value),
+            Number(value) => write!(f, "Number: {}", value),
+            Bool(value) => write!(f, "Bool: {}", value),
+            String(value) => write!(f, "String: {}", value.as_ref()),
         }
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
-pub enum ExprAST<'a> {
-    Literal(Literal<'a>),
-    Unary(&'a str, Box<ExprAST<'a>>),
-    Binary(&'a str, Box<ExprAST<'a>>, Box<ExprAST<'a>>),
-    Postfix(Box<ExprAST<'a>>, String),
-    Ternary(Box<ExprAST<'a>>, Box<ExprAST<'a>>, Box<ExprAST<'a>>),
-    Reference(&'a str),
-    Function(&'a str, Vec<ExprAST<'a>>),
-    List(Vec<ExprAST<'a>>),
-    Map(Vec<(ExprAST<'a>, ExprAST<'a>)>),
-    Stmt(Vec<ExprAST<'a>>),
+/// The expression tree, generic over how its names and string literals are stored.
+/// The parser produces the borrowed [`ExprAST`], while [`Expr<String>`] owns its
+/// strings and can outlive the source text.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Expr<S> {
+    Literal(Literal<S>),
+    Unary(S, Box<Expr<S>>),
+    Binary(S, Box<Expr<S>>, Box<Expr<S>>),
+    Postfix(Box<Expr<S>>, String),
+    Index(Box<Expr<S>>, Box<Expr<S>>),
+    Ternary(Box<Expr<S>>, Box<Expr<S>>, Box<Expr<S>>),
+    Reference(S),
+    Function(S, Vec<Expr<S>>),
+    List(Vec<Expr<S>>),
+    Map(Vec<(Expr<S>, Expr<S>)>),
+    Stmt(Vec<Expr<S>>),
     None,
 }
 
+pub type ExprAST<'a> = Expr<&'a str>;
+
+/// Constructors for building an owned tree in code instead of parsing it, e.g.
+/// `Expr::binary("+", Expr::reference("a"), Expr::number(3))`.
+impl Expr<String> {
+    pub fn number(value: impl Into<Decimal>) -> Self {
+        Self::Literal(Literal::Number(value.into()))
+    }
+
+    pub fn string(value: &str) -> Self {
+        Self::Literal(Literal::String(value.to_string()))
+    }
+
+    pub fn bool(value: bool) -> Self {
+        Self::Literal(Literal::Bool(value))
+    }
+
+    pub fn reference(name: &str) -> Self {
+        Self::Reference(name.to_string())
+    }
+
+    pub fn unary(op: &str, rhs: Self) -> Self {
+        Self::Unary(op.to_string(), Box::new(rhs))
+    }
+
+    pub fn binary(op: &str, lhs: Self, rhs: Self) -> Self {
+        Self::Binary(op.to_string(), Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn postfix(lhs: Self, op: &str) -> Self {
+        Self::Postfix(Box::new(lhs), op.to_string())
+    }
+
+    pub fn index(lhs: Self, index: Self) -> Self {
+        Self::Index(Box::new(lhs), Box::new(index))
+    }
+
+    pub fn ternary(condition: Self, lhs: Self, rhs: Self) -> Self {
+        Self::Ternary(Box::new(condition), Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn function(name: &str, params: Vec<Self>) -> Self {
+        Self::Function(name.to_string(), params)
+    }
+
+    pub fn list(values: Vec<Self>) -> Self {
+        Self::List(values)
+    }
+
+    pub fn map(entries: Vec<(Self, Self)>) -> Self {
+        Self::Map(entries)
+    }
+}
+
 #[cfg(not(tarpaulin_include))]
-impl<'a> fmt::Display for ExprAST<'a> {
+impl<S: AsRef<str>> fmt::Display for Expr<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Literal(val) => write!(f, "Literal AST: {}", val.clone()),
+            Self::Literal(val) => write!(f, "Literal AST: {}", val),
             Self::Unary(op, rhs) => {
-                write!(f, "Unary AST: Op: {}, Rhs: {}", op, rhs.clone())
+                write!(f, "Unary AST: Op: {}, Rhs: {}", op.as_ref(), rhs)
             }
             Self::Binary(op, lhs, rhs) => write!(
                 f,
                 "Binary AST: Op: {}, Lhs: {}, Rhs: {}",
-                op,
-                lhs.clone(),
-                rhs.clone()
+                op.as_ref(),
+                lhs,
+                rhs
             ),
             Self::Postfix(lhs, op) => {
-                write!(f, "Postfix AST: Lhs: {}, Op: {}", lhs.clone(), op.clone(),)
+                write!(f, "Postfix AST: Lhs: {}, Op: {}", lhs, op,)
+            }
+            Self::Index(lhs, index) => {
+                write!(f, "Index AST: Lhs: {}, Index: {}", lhs, index)
             }
             Self::Ternary(condition, lhs, rhs) => write!(
                 f,
                 "Ternary AST: Condition: {}, Lhs: {}, Rhs: {}",
-                condition.clone(),
-                lhs.clone(),
-                rhs.clone()
+                condition, lhs, rhs
             ),
-            Self::Reference(name) => write!(f, "Reference AST: reference: {}", name),
+            Self::Reference(name) => write!(f, "Reference AST: reference: {}", name.as_ref()),
             Self::Function(name, params) => {
                 let mut s = "[".to_string();
-                for param in params.into_iter() {
-                    s.push_str(format!("{},", param.clone()).as_str());
+                for param in params.iter() {
+                    s.push_str(format!("{},", param).as_str());
                 }
                 s.push(']');
-                write!(f, "Function AST: name: {}, params: {}", name, s)
+                write!(f, "Function AST: name: {}, params: {}", name.as_ref(), s)
             }
             Self::List(params) => {
                 let mut s = "[".to_string();
-                for param in params.into_iter() {
-                    s.push_str(format!("{},", param.clone()).as_str());
+                for param in params.iter() {
+                    s.push_str(format!("{},", param).as_str());
                 }
                 s.push(']');
                 write!(f, "List AST: params: {}", s)
@@ -89,14 +153,14 @@ impl<'a> fmt::Display for ExprAST<'a> {
             Self::Map(m) => {
                 let mut s = String::new();
                 for (k, v) in m {
-                    s.push_str(format!("({} {}), ", k.clone(), v.clone()).as_str());
+                    s.push_str(format!("({} {}), ", k, v).as_str());
                 }
                 write!(f, "Map AST: {}", s)
             }
             Self::Stmt(exprs) => {
                 let mut s = String::new();
                 for expr in exprs {
-                    s.push_str(format!("{};", expr.clone()).as_str());
+                    s.push_str(format!("{};", expr).as_str());
                 }
                 write!(f, "Chain AST: {}", s)
             }
@@ -105,45 +169,65 @@ impl<'a> fmt::Display for ExprAST<'a> {
     }
 }
 
-impl<'a> ExprAST<'a> {
+impl<S: AsRef<str> + Clone> Expr<S> {
     pub fn exec(&self, ctx: &mut Context) -> Result<Value> {
-        use ExprAST::*;
+        use Expr::*;
+        ctx.step()?;
         match self {
-            Literal(literal) => self.exec_literal(literal.clone()),
-            Reference(name) => self.exec_reference(name, ctx),
-            Function(name, exprs) => self.exec_function(name, exprs.clone(), ctx),
-            Unary(op, rhs) => self.exec_unary(op, rhs, ctx),
-            Binary(op, lhs, rhs) => self.exec_binary(op, lhs, rhs, ctx),
-            Postfix(lhs, op) => self.exec_postfix(lhs, op.clone(), ctx),
+            Literal(literal) => self.exec_literal(literal),
+            Reference(name) => self.exec_reference(name.as_ref(), ctx),
+            Function(name, exprs) if name.as_ref() == "if" => self.exec_if(exprs, ctx),
+            Function(name, exprs) => self.exec_function(name.as_ref(), exprs, ctx),
+            Unary(op, rhs) => self.exec_unary(op.as_ref(), rhs, ctx),
+            Binary(op, lhs, rhs) => self.exec_binary(op.as_ref(), lhs, rhs, ctx),
+            Postfix(lhs, op) => self.exec_postfix(lhs, op, ctx),
+            Index(lhs, index) => self.exec_index(lhs, index, ctx),
             Ternary(condition, lhs, rhs) => self.exec_ternary(condition, lhs, rhs, ctx),
-            List(params) => self.exec_list(params.clone(), ctx),
-            Stmt(exprs) => self.exec_chain(exprs.clone(), ctx),
-            Map(m) => self.exec_map(m.clone(), ctx),
+            List(params) => self.exec_list(params, ctx),
+            Stmt(exprs) => self.exec_chain(exprs, ctx),
+            Map(m) => self.exec_map(m, ctx),
             None => Ok(Value::None),
         }
     }
 
-    fn exec_literal(&self, literal: Literal<'a>) -> Result<Value> {
+    /// Like `exec`, but lends the result to `f` instead of returning it. A plain
+    /// reference to a variable is lent straight from the context, which avoids
+    /// cloning large lists and maps that are only read.
+    ///
+    /// # Deadlocks
+    ///
+    /// While `f` runs on a lent variable, the context's bindings stay locked. `f`
+    /// must not use `ctx` or any context sharing its bindings through
+    /// `shared_clone`, e.g. to evaluate another expression or to read or set a
+    /// variable, or it will deadlock. Clone the value out of `f` first if you
+    /// need to.
+    pub fn exec_with<R>(&self, ctx: &mut Context, f: impl FnOnce(&Value) -> R) -> Result<R> {
+        if let Expr::Reference(name) = self {
+            ctx.track(name.as_ref());
+            let binding = ctx.store.lock();
+            if let Some(ContextValue::Variable(value)) = binding.get(name.as_ref()) {
+                return Ok(f(value));
+            }
+        }
+        Ok(f(&self.exec(ctx)?))
+    }
+
+    fn exec_literal(&self, literal: &Literal<S>) -> Result<Value> {
         match literal {
-            Literal::Bool(value) => Ok(Value::from(value)),
-            Literal::Number(value) => Ok(Value::from(value)),
-            Literal::String(value) => Ok(Value::from(value)),
+            Literal::Bool(value) => Ok(Value::from(*value)),
+            Literal::Number(value) => Ok(Value::from(*value)),
+            Literal::String(value) => Ok(Value::from(value.as_ref())),
         }
     }
 
-    fn exec_reference(&self, name: &'a str, ctx: &Context) -> Result<Value> {
+    fn exec_reference(&self, name: &str, ctx: &Context) -> Result<Value> {
         ctx.value(name)
     }
 
-    fn exec_function(
-        &self,
-        name: &'a str,
-        exprs: Vec<ExprAST<'a>>,
-        ctx: &mut Context,
-    ) -> Result<Value> {
-        let mut params: Vec<Value> = Vec::new();
-        for expr in exprs.into_iter() {
-            params.push(expr.exec(ctx)?)
+    fn exec_function(&self, name: &str, exprs: &[Expr<S>], ctx: &mut Context) -> Result<Value> {
+        let mut params: Vec<Value> = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            params.push(Self::exec_param(expr, ctx)?)
         }
         match ctx.get_func(name) {
             Some(func) => func(params),
@@ -151,45 +235,113 @@ impl<'a> ExprAST<'a> {
         }
     }
 
+    // A bare reference to a function is passed as a `Value::Function` instead of
+    // being called, so `map(xs, f)` works, and so are the elements of a list
+    // literal, as in `sortByKeys(xs, [f, g])`.
+    fn exec_param(expr: &Expr<S>, ctx: &mut Context) -> Result<Value> {
+        match expr {
+            Expr::Reference(name) => match ctx.callable(name.as_ref()) {
+                Some(func) => {
+                    ctx.step()?;
+                    Ok(Value::Function(func))
+                }
+                None => expr.exec(ctx),
+            },
+            Expr::List(params) => {
+                ctx.step()?;
+                let mut ans = Vec::with_capacity(params.len());
+                for expr in params {
+                    ans.push(Self::exec_param(expr, ctx)?);
+                }
+                Ok(Value::List(ans))
+            }
+            _ => expr.exec(ctx),
+        }
+    }
+
     fn redirect_inner_function(&self, name: &str, params: Vec<Value>) -> Result<Value> {
         InnerFunctionManager::new().get(name)?(params)
     }
 
-    fn exec_unary(&self, op: &'a str, rhs: &ExprAST, ctx: &mut Context) -> Result<Value> {
-        PrefixOpManager::new().get(&op)?(rhs.exec(ctx)?)
+    fn exec_unary(&self, op: &str, rhs: &Expr<S>, ctx: &mut Context) -> Result<Value> {
+        ctx.check_operator(op)?;
+        PrefixOpManager::new().get(op)?(rhs.exec(ctx)?)
     }
 
     fn exec_binary(
         &self,
-        op: &'a str,
-        lhs: &ExprAST<'a>,
-        rhs: &ExprAST<'a>,
+        op: &str,
+        lhs: &Expr<S>,
+        rhs: &Expr<S>,
         ctx: &mut Context,
     ) -> Result<Value> {
-        match InfixOpManager::new().get_op_type(&op)? {
+        ctx.check_operator(op)?;
+        match InfixOpManager::new().get_op_type(op)? {
             InfixOpType::CALC => {
-                InfixOpManager::new().get_handler(&op)?(lhs.exec(ctx)?, rhs.exec(ctx)?)
+                let a = match (op, lhs) {
+                    ("??", Expr::Reference(name)) => ctx.value_lenient(name.as_ref())?,
+                    _ => lhs.exec(ctx)?,
+                };
+                match (op, &a) {
+                    ("&&", Value::Bool(false)) | ("||", Value::Bool(true)) => return Ok(a),
+                    ("??", a) if *a != Value::None => return Ok(a.clone()),
+                    _ => (),
+                }
+                let b = rhs.exec(ctx)?;
+                InfixOpManager::new().get_typed_handler(op, &a, &b)?(a, b)
             }
             InfixOpType::SETTER => {
-                let (a, b) = (lhs.exec(ctx)?, rhs.exec(ctx)?);
+                let a = match (op, lhs) {
+                    // Plain assignment overwrites the variable without reading it, so
+                    // lazy initializers and function bindings don't run.
+                    ("=", Expr::Reference(_)) => Value::None,
+                    ("??=", Expr::Reference(name)) => ctx.value_lenient(name.as_ref())?,
+                    _ => lhs.exec(ctx)?,
+                };
+                let b = rhs.exec(ctx)?;
                 ctx.set_variable(
                     lhs.get_reference_name()?,
-                    InfixOpManager::new().get_handler(&op)?(a, b)?,
+                    InfixOpManager::new().get_typed_handler(op, &a, &b)?(a, b)?,
                 );
                 Ok(Value::None)
             }
         }
     }
 
-    fn exec_postfix(&self, lhs: &ExprAST, op: String, ctx: &mut Context) -> Result<Value> {
-        PostfixOpManager::new().get(&op)?(lhs.exec(ctx)?)
+    fn exec_postfix(&self, lhs: &Expr<S>, op: &str, ctx: &mut Context) -> Result<Value> {
+        ctx.check_operator(op)?;
+        PostfixOpManager::new().get(op)?(lhs.exec(ctx)?)
+    }
+
+    // Lists and strings take integer indices, where negative ones count from the
+    // end. Maps are indexed by key, and missing keys evaluate to `Value::None`.
+    fn exec_index(&self, lhs: &Expr<S>, index: &Expr<S>, ctx: &mut Context) -> Result<Value> {
+        let value = lhs.exec(ctx)?;
+        let index = index.exec(ctx)?;
+        match value {
+            Value::List(values) => {
+                let i = index.integer()?;
+                let pos = position(values.len(), i)?;
+                Ok(values.into_iter().nth(pos).unwrap())
+            }
+            Value::String(s) => {
+                let i = index.integer()?;
+                let pos = position(s.chars().count(), i)?;
+                Ok(Value::from(s.chars().nth(pos).unwrap().to_string()))
+            }
+            Value::Map(m) => Ok(m
+                .into_iter()
+                .find(|(k, _)| *k == index)
+                .map_or(Value::None, |(_, v)| v)),
+            _ => Err(Error::ShouldBeList()),
+        }
     }
 
     fn exec_ternary(
         &self,
-        condition: &ExprAST,
-        lhs: &ExprAST,
-        rhs: &ExprAST,
+        condition: &Expr<S>,
+        lhs: &Expr<S>,
+        rhs: &Expr<S>,
         ctx: &mut Context,
     ) -> Result<Value> {
         match condition.exec(ctx)? {
@@ -203,15 +355,22 @@ impl<'a> ExprAST<'a> {
         }
     }
 
-    fn exec_list(&self, params: Vec<ExprAST>, ctx: &mut Context) -> Result<Value> {
-        let mut ans = Vec::new();
+    fn exec_if(&self, params: &[Expr<S>], ctx: &mut Context) -> Result<Value> {
+        match params {
+            [condition, lhs, rhs] => self.exec_ternary(condition, lhs, rhs, ctx),
+            _ => Err(Error::ParamInvalid()),
+        }
+    }
+
+    fn exec_list(&self, params: &[Expr<S>], ctx: &mut Context) -> Result<Value> {
+        let mut ans = Vec::with_capacity(params.len());
         for expr in params {
             ans.push(expr.exec(ctx)?);
         }
         Ok(Value::List(ans))
     }
 
-    fn exec_chain(&self, params: Vec<ExprAST>, ctx: &mut Context) -> Result<Value> {
+    fn exec_chain(&self, params: &[Expr<S>], ctx: &mut Context) -> Result<Value> {
         let mut ans = Value::None;
         for expr in params {
             ans = expr.exec(ctx)?;
@@ -219,8 +378,8 @@ impl<'a> ExprAST<'a> {
         Ok(ans)
     }
 
-    fn exec_map(&self, m: Vec<(ExprAST, ExprAST)>, ctx: &mut Context) -> Result<Value> {
-        let mut ans = Vec::new();
+    fn exec_map(&self, m: &[(Expr<S>, Expr<S>)], ctx: &mut Context) -> Result<Value> {
+        let mut ans = Vec::with_capacity(m.len());
         for (k, v) in m {
             ans.push((k.exec(ctx)?, v.exec(ctx)?));
         }
@@ -229,37 +388,126 @@ impl<'a> ExprAST<'a> {
 
     fn get_precidence(&self) -> (bool, (i32, i32)) {
         match self {
-            ExprAST::Binary(op, _, _) => (true, InfixOpManager::new().get_precidence(op)),
+            Expr::Binary(op, _, _) => (true, InfixOpManager::new().get_precidence(op.as_ref())),
             _ => (false, (-1, -1)),
         }
     }
 
-    fn get_reference_name(&self) -> Result<&'a str> {
+    fn binary_op(&self) -> Option<&str> {
+        match self {
+            Expr::Binary(op, _, _) => Some(op.as_ref()),
+            _ => None,
+        }
+    }
+
+    fn get_reference_name(&self) -> Result<&str> {
         match self {
-            ExprAST::Reference(name) => Ok(name),
+            Expr::Reference(name) => Ok(name.as_ref()),
             _ => Err(Error::NotReferenceExpr),
         }
     }
+
+    pub fn has_assignment(&self) -> bool {
+        use Expr::*;
+        match self {
+            Binary(op, lhs, rhs) => {
+                let is_setter = matches!(
+                    InfixOpManager::new().get_op_type(op.as_ref()),
+                    Ok(InfixOpType::SETTER)
+                );
+                is_setter || lhs.has_assignment() || rhs.has_assignment()
+            }
+            Unary(_, rhs) => rhs.has_assignment(),
+            Postfix(lhs, _) => lhs.has_assignment(),
+            Index(lhs, index) => lhs.has_assignment() || index.has_assignment(),
+            Ternary(condition, lhs, rhs) => {
+                condition.has_assignment() || lhs.has_assignment() || rhs.has_assignment()
+            }
+            Function(_, exprs) | List(exprs) | Stmt(exprs) => {
+                exprs.iter().any(|expr| expr.has_assignment())
+            }
+            Map(m) => m
+                .iter()
+                .any(|(k, v)| k.has_assignment() || v.has_assignment()),
+            Literal(_) | Reference(_) | None => false,
+        }
+    }
+
+    /// Converts the tree into one with owned strings, so it no longer borrows the source text.
+    pub fn to_owned_ast(&self) -> Expr<String> {
+        self.map_str(&mut |s| s.to_string())
+    }
+
+    /// Like `to_owned_ast`, but equal strings share a single allocation, which pays
+    /// off for long rules that repeat the same names and literals.
+    pub fn to_interned_ast(&self) -> Expr<Arc<str>> {
+        let mut table: HashSet<Arc<str>> = HashSet::new();
+        self.map_str(&mut |s| match table.get(s) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Arc<str> = Arc::from(s);
+                table.insert(interned.clone());
+                interned
+            }
+        })
+    }
+
+    pub(crate) fn map_str<T>(&self, f: &mut impl FnMut(&str) -> T) -> Expr<T> {
+        use Expr::*;
+        match self {
+            Literal(literal) => Literal(match literal {
+                self::Literal::Number(value) => self::Literal::Number(*value),
+                self::Literal::Bool(value) => self::Literal::Bool(*value),
+                self::Literal::String(value) => self::Literal::String(f(value.as_ref())),
+            }),
+            Unary(op, rhs) => Unary(f(op.as_ref()), Box::new(rhs.map_str(f))),
+            Binary(op, lhs, rhs) => Binary(
+                f(op.as_ref()),
+                Box::new(lhs.map_str(f)),
+                Box::new(rhs.map_str(f)),
+            ),
+            Postfix(lhs, op) => Postfix(Box::new(lhs.map_str(f)), op.clone()),
+            Index(lhs, index) => Index(Box::new(lhs.map_str(f)), Box::new(index.map_str(f))),
+            Ternary(condition, lhs, rhs) => Ternary(
+                Box::new(condition.map_str(f)),
+                Box::new(lhs.map_str(f)),
+                Box::new(rhs.map_str(f)),
+            ),
+            Reference(name) => Reference(f(name.as_ref())),
+            Function(name, exprs) => Function(
+                f(name.as_ref()),
+                exprs.iter().map(|expr| expr.map_str(f)).collect(),
+            ),
+            List(exprs) => List(exprs.iter().map(|expr| expr.map_str(f)).collect()),
+            Map(m) => Map(m
+                .iter()
+                .map(|(k, v)| (k.map_str(f), v.map_str(f)))
+                .collect()),
+            Stmt(exprs) => Stmt(exprs.iter().map(|expr| expr.map_str(f)).collect()),
+            None => None,
+        }
+    }
 }
 
-impl<'a> ExprAST<'a> {
+impl<S: AsRef<str> + Clone> Expr<S> {
     pub fn expr(&self) -> String {
         match self {
             Self::Literal(val) => self.literal_expr(val.clone()),
-            Self::Reference(name) => self.reference_expr(name),
-            Self::Function(name, exprs) => self.function_expr(name, exprs.clone()),
-            Self::Unary(op, rhs) => self.unary_expr(op, rhs),
-            Self::Binary(op, lhs, rhs) => self.binary_expr(op, lhs, rhs),
+            Self::Reference(name) => self.reference_expr(name.as_ref()),
+            Self::Function(name, exprs) => self.function_expr(name.as_ref(), exprs.clone()),
+            Self::Unary(op, rhs) => self.unary_expr(op.as_ref(), rhs),
+            Self::Binary(op, lhs, rhs) => self.binary_expr(op.as_ref(), lhs, rhs),
             Self::Postfix(lhs, op) => self.postfix_expr(lhs, op),
+            Self::Index(lhs, index) => self.index_expr(lhs, index),
             Self::Ternary(condition, lhs, rhs) => self.ternary_expr(condition, lhs, rhs),
             Self::List(params) => self.list_expr(params.clone()),
             Self::Map(m) => self.map_expr(m.clone()),
             Self::Stmt(exprs) => self.chain_expr(exprs.clone()),
-            Self::None => "".to_string(),
+            Self::None => "null".to_string(),
         }
     }
 
-    fn literal_expr(&self, val: Literal) -> String {
+    fn literal_expr(&self, val: Literal<S>) -> String {
         use Literal::*;
         match val {
             Number(value) => value.to_string(),
@@ -270,15 +518,15 @@ impl<'a> ExprAST<'a> {
                     "false".into()
                 }
             }
-            String(value) => "\"".to_string() + &value + "\"",
+            String(value) => "\"".to_string() + value.as_ref() + "\"",
         }
     }
 
-    fn reference_expr(&self, val: &'a str) -> String {
+    fn reference_expr(&self, val: &str) -> String {
         val.to_string()
     }
 
-    fn function_expr(&self, name: &'a str, exprs: Vec<ExprAST>) -> String {
+    fn function_expr(&self, name: &str, exprs: Vec<Expr<S>>) -> String {
         let mut ans = name.to_string();
         ans.push('(');
         for i in 0..exprs.len() {
@@ -291,39 +539,66 @@ impl<'a> ExprAST<'a> {
         ans
     }
 
-    fn unary_expr(&self, op: &'a str, rhs: &ExprAST) -> String {
-        op.to_string() + " " + &rhs.expr()
+    fn unary_expr(&self, op: &str, rhs: &Expr<S>) -> String {
+        let operand = match rhs {
+            Self::Binary(..) | Self::Ternary(..) => rhs.paren_expr(),
+            _ => rhs.expr(),
+        };
+        op.to_string() + " " + &operand
     }
 
-    fn binary_expr(&self, op: &'a str, lhs: &ExprAST, rhs: &ExprAST) -> String {
-        let left = {
-            let (is, precidence) = lhs.get_precidence();
-            let mut tmp: String = lhs.expr();
-            if is && precidence < InfixOpManager::new().get_precidence(op) {
-                tmp = "(".to_string() + &lhs.expr() + &")".to_string();
+    fn binary_expr(&self, op: &str, lhs: &Expr<S>, rhs: &Expr<S>) -> String {
+        let left = lhs.binary_operand_expr(op, false);
+        let right = rhs.binary_operand_expr(op, true);
+        left + " " + op + " " + &right
+    }
+
+    // An operand is parenthesized when it binds looser than the operator, or equally
+    // loose on the side the operator doesn't associate to, e.g. the rhs of `a - (b - c)`.
+    fn binary_operand_expr(&self, op: &str, is_rhs: bool) -> String {
+        let need_paren = match self {
+            Self::Ternary(..) => true,
+            Self::Binary(..) => {
+                let (_, (precidence, _)) = self.get_precidence();
+                let (l_bp, r_bp) = InfixOpManager::new().get_precidence(op);
+                let is_left_associative = r_bp > l_bp;
+                precidence < l_bp || (precidence == l_bp && is_rhs == is_left_associative)
             }
-            tmp
+            _ => false,
         };
-        let right = {
-            let (is, precidence) = rhs.get_precidence();
-            let mut tmp = rhs.expr();
-            if is && precidence < InfixOpManager::new().get_precidence(op) {
-                tmp = "(".to_string() + &rhs.expr() + &")".to_string();
-            }
-            tmp
+        if need_paren {
+            return self.paren_expr();
+        }
+        self.expr()
+    }
+
+    fn paren_expr(&self) -> String {
+        "(".to_string() + &self.expr() + ")"
+    }
+
+    fn postfix_expr(&self, lhs: &Expr<S>, op: &str) -> String {
+        let operand = match lhs {
+            Self::Unary(..) | Self::Binary(..) | Self::Ternary(..) => lhs.paren_expr(),
+            _ => lhs.expr(),
         };
-        left + " " + op + " " + &right
+        operand + " " + op
     }
 
-    fn postfix_expr(&self, lhs: &ExprAST, op: &str) -> String {
-        lhs.expr() + " " + op
+    fn index_expr(&self, lhs: &Expr<S>, index: &Expr<S>) -> String {
+        let operand = match lhs {
+            Self::Unary(..) | Self::Binary(..) | Self::Postfix(..) | Self::Ternary(..) => {
+                lhs.paren_expr()
+            }
+            _ => lhs.expr(),
+        };
+        operand + "[" + &index.expr() + "]"
     }
 
-    fn ternary_expr(&self, condition: &ExprAST, lhs: &ExprAST, rhs: &ExprAST) -> String {
+    fn ternary_expr(&self, condition: &Expr<S>, lhs: &Expr<S>, rhs: &Expr<S>) -> String {
         condition.expr() + " ? " + &lhs.expr() + " : " + &rhs.expr()
     }
 
-    fn list_expr(&self, params: Vec<ExprAST>) -> String {
+    fn list_expr(&self, params: Vec<Expr<S>>) -> String {
         let mut s = String::from("[");
         for i in 0..params.len() {
             s.push_str(params[i].expr().as_str());
@@ -335,7 +610,7 @@ impl<'a> ExprAST<'a> {
         s
     }
 
-    fn map_expr(&self, m: Vec<(ExprAST, ExprAST)>) -> String {
+    fn map_expr(&self, m: Vec<(Expr<S>, Expr<S>)>) -> String {
         let mut s = String::from("{");
         for i in 0..m.len() {
             let (key, value) = m[i].clone();
@@ -350,7 +625,7 @@ impl<'a> ExprAST<'a> {
         s
     }
 
-    fn chain_expr(&self, exprs: Vec<ExprAST>) -> String {
+    fn chain_expr(&self, exprs: Vec<Expr<S>>) -> String {
         let mut s = String::new();
         for i in 0..exprs.len() {
             s.push_str(exprs[i].expr().as_str());
@@ -362,59 +637,125 @@ impl<'a> ExprAST<'a> {
     }
 }
 
-impl<'a> ExprAST<'a> {
+impl<S: AsRef<str> + Clone> Expr<S> {
     pub fn describe(&self) -> String {
+        self.render(&DescriptorManager::new())
+    }
+
+    /// Like `describe`, but with the descriptors of `manager` instead of the
+    /// global ones, see `DescriptorManager::local`.
+    pub fn render(&self, manager: &DescriptorManager) -> String {
+        self.describe_with(manager, &mut |expr| expr.render(manager))
+    }
+
+    /// Like `describe`, but the output of every node is passed through the
+    /// decorator registered for it in the `DecoratorManager`, children first.
+    pub fn decorate(&self) -> String {
+        let s = self.describe_with(&DescriptorManager::new(), &mut |expr| expr.decorate());
+        let key = match self {
+            Self::Literal(_) => DecoratorKey::Literal,
+            Self::Unary(op, _) => DecoratorKey::Unary(op.as_ref().to_string()),
+            Self::Binary(op, _, _) => DecoratorKey::Binary(op.as_ref().to_string()),
+            Self::Postfix(_, op) => DecoratorKey::Postfix(op.clone()),
+            Self::Index(..) => DecoratorKey::Index,
+            Self::Ternary(_, _, _) => DecoratorKey::Ternary,
+            Self::Function(name, _) => DecoratorKey::Function(name.as_ref().to_string()),
+            Self::Reference(name) => DecoratorKey::Reference(name.as_ref().to_string()),
+            Self::List(_) => DecoratorKey::List,
+            Self::Map(_) => DecoratorKey::Map,
+            Self::Stmt(_) => DecoratorKey::Chain,
+            Self::None => DecoratorKey::None,
+        };
+        DecoratorManager::new().decorate(&key, s)
+    }
+
+    /// Like `describe`, but memoizes the description of every node in `cache`, so
+    /// identical subtrees are described once. Reuse the cache to render the same
+    /// ASTs repeatedly; it must be cleared if descriptors are re-registered.
+    pub fn describe_cached<'b>(&'b self, cache: &mut HashMap<&'b Expr<S>, String>) -> String
+    where
+        S: Hash + Eq,
+    {
+        if let Some(s) = cache.get(self) {
+            return s.clone();
+        }
+        let s = self.describe_with(&DescriptorManager::new(), &mut |expr| {
+            expr.describe_cached(cache)
+        });
+        cache.insert(self, s.clone());
+        s
+    }
+
+    fn describe_with<'b>(
+        &'b self,
+        manager: &DescriptorManager,
+        f: &mut impl FnMut(&'b Expr<S>) -> String,
+    ) -> String {
         match self {
+            Self::Literal(Literal::String(s)) => {
+                manager.get_string_descriptor()(s.as_ref().to_string())
+            }
             Self::Literal(_) => self.expr(),
-            Self::Unary(op, rhs) => DescriptorManager::new().get_unary_descriptor(op.to_string())(
-                op.to_string(),
-                rhs.describe(),
+            Self::Unary(op, rhs) => manager.get_unary_descriptor(op.as_ref().to_string())(
+                op.as_ref().to_string(),
+                f(rhs),
             ),
-            Self::Binary(op, lhs, rhs) => DescriptorManager::new()
-                .get_binary_descriptor(op.to_string())(
-                op.to_string(),
-                lhs.describe(),
-                rhs.describe(),
+            Self::Binary(op, lhs, rhs) => manager.get_binary_descriptor(op.as_ref().to_string())(
+                BinaryOpMeta::new(op.as_ref()).with_operands(lhs.binary_op(), rhs.binary_op()),
+                f(lhs),
+                f(rhs),
             ),
-            Self::Postfix(lhs, op) => DescriptorManager::new().get_postfix_descriptor(op.clone())(
-                lhs.describe(),
-                op.clone(),
-            ),
-            Self::List(values) => DescriptorManager::new().get_list_descriptor()(
-                values.into_iter().map(|v| v.describe()).collect(),
-            ),
-            Self::Map(values) => DescriptorManager::new().get_map_descriptor()(
-                values
-                    .into_iter()
-                    .map(|value| (value.0.describe(), value.1.describe()))
-                    .collect(),
-            ),
-            Self::Function(name, values) => DescriptorManager::new()
-                .get_function_descriptor(name.to_string())(
-                name.to_string(),
-                values.into_iter().map(|v| v.describe()).collect(),
-            ),
-            Self::Reference(name) => DescriptorManager::new()
-                .get_reference_descriptor(name.to_string())(
-                name.to_string()
+            Self::Postfix(lhs, op) => {
+                manager.get_postfix_descriptor(op.clone())(f(lhs), op.clone())
+            }
+            Self::List(values) => {
+                manager.get_list_descriptor()(values.iter().map(&mut *f).collect())
+            }
+            Self::Map(values) => {
+                manager.get_map_descriptor()(values.iter().map(|(k, v)| (f(k), f(v))).collect())
+            }
+            Self::Function(name, values) => manager
+                .get_function_descriptor(name.as_ref().to_string())(
+                name.as_ref().to_string(),
+                values.iter().map(&mut *f).collect(),
             ),
-            Self::Stmt(values) => DescriptorManager::new().get_chain_descriptor()(
-                values.into_iter().map(|v| v.describe()).collect(),
+            Self::Reference(name) => manager.get_reference_descriptor(name.as_ref().to_string())(
+                name.as_ref().to_string(),
             ),
+            Self::Stmt(values) => {
+                manager.get_chain_descriptor()(values.iter().map(&mut *f).collect())
+            }
             Self::Ternary(condition, lhs, rhs) => {
-                DescriptorManager::new().get_ternary_descriptor()(
-                    condition.describe(),
-                    lhs.describe(),
-                    rhs.describe(),
-                )
+                manager.get_ternary_descriptor()(f(condition), f(lhs), f(rhs))
             }
-            Self::None => "".to_string(),
+            Self::Index(lhs, index) => f(lhs) + "[" + &f(index) + "]",
+            Self::None => "null".to_string(),
         }
     }
 }
 
+// Maps an index, which counts from the end when negative, to a position in a
+// sequence of the given length.
+fn position(len: usize, index: i64) -> Result<usize> {
+    let pos = if index < 0 { len as i64 + index } else { index };
+    if pos < 0 || pos >= len as i64 {
+        return Err(Error::IndexOutOfRange(index));
+    }
+    Ok(pos as usize)
+}
+
+/// The default maximum depth of the expressions the parser accepts, see
+/// `Parser::set_max_depth`. Debug builds use several kilobytes of stack per
+/// level, so this leaves room to spare on a 2 MB thread stack.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
 pub struct Parser<'a> {
     tokenizer: Tokenizer<'a>,
+    depth: usize,
+    max_depth: usize,
+    // The spans of the parsed nodes in post-order, kept only when the parser was
+    // created by `with_spans`.
+    spans: Option<Vec<Span>>,
 }
 
 impl<'a> Parser<'a> {
@@ -426,10 +767,94 @@ impl<'a> Parser<'a> {
         let mut tokenizer = Tokenizer::new(input);
         tokenizer.next()?;
         Ok(Self {
-            tokenizer: tokenizer,
+            tokenizer,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            spans: None,
+        })
+    }
+
+    /// Creates a parser that keeps comments, see `parse_stmt_with_comments`.
+    pub fn with_comments(input: &'a str) -> Result<Self> {
+        let mut tokenizer = Tokenizer::with_comments(input);
+        tokenizer.next()?;
+        Ok(Self {
+            tokenizer,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            spans: None,
+        })
+    }
+
+    /// Creates a parser that records where each node starts and ends in the
+    /// input, see `take_spans`.
+    pub fn with_spans(input: &'a str) -> Result<Self> {
+        Ok(Self {
+            spans: Some(Vec::new()),
+            ..Self::new(input)?
         })
     }
 
+    /// Returns the spans of the nodes parsed so far and clears them. A node's span
+    /// covers its source text in bytes, including enclosing parentheses, and the
+    /// spans are in post-order: children first, in source order, then the node.
+    pub fn take_spans(&mut self) -> Vec<Span> {
+        self.spans.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // The offset of the current token, where the next node starts.
+    fn start(&self) -> usize {
+        self.tokenizer.cur_token.span().map_or(0, |span| span.0)
+    }
+
+    // The offset of the last completed node, e.g. the left operand of a binary
+    // operator that is being parsed.
+    fn last_start(&self) -> usize {
+        self.spans
+            .as_ref()
+            .and_then(|spans| spans.last())
+            .map_or(0, |span| span.0)
+    }
+
+    // Records the span of a node that started at `start` and ends with the last
+    // consumed token, right before the node is built.
+    fn record(&mut self, start: usize) {
+        if let Some(spans) = self.spans.as_mut() {
+            let end = self
+                .tokenizer
+                .prev_token
+                .span()
+                .map_or(start, |span| span.1);
+            spans.push(Span(start, end));
+        }
+    }
+
+    /// Sets how deeply expressions may nest before parsing fails with
+    /// `Error::ExpressionTooDeep`, which keeps hostile input from overflowing the
+    /// stack. Every parenthesis, bracket, brace, unary operator, ternary branch
+    /// and chained binary operator adds a level, e.g. `(1)` and `1 + 2` have a
+    /// depth of 1 and `-(1 + 2 + 3)` a depth of 4.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // Parses an operand one level deeper than the current one. The operands of
+    // the top-level expression are at depth 0.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.check_depth(self.depth)?;
+        self.depth += 1;
+        let ans = f(self);
+        self.depth -= 1;
+        ans
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<()> {
+        if depth > self.max_depth {
+            return Err(Error::ExpressionTooDeep(self.max_depth));
+        }
+        Ok(())
+    }
+
     fn is_eof(&self) -> bool {
         self.cur_tok().is_eof()
     }
@@ -444,24 +869,41 @@ impl<'a> Parser<'a> {
 
     fn parse_token(&mut self) -> Result<ExprAST<'a>> {
         let token = self.tokenizer.cur_token;
+        let start = self.start();
         match token {
             Token::Number(val, _) => {
                 self.next()?;
+                self.record(start);
                 Ok(ExprAST::Literal(Literal::Number(val)))
             }
             Token::Bool(val, _) => {
                 self.next()?;
+                self.record(start);
                 Ok(ExprAST::Literal(Literal::Bool(val)))
             }
+            Token::Null(_) => {
+                self.next()?;
+                self.record(start);
+                Ok(ExprAST::None)
+            }
             Token::String(val, _) => {
                 self.next()?;
+                self.record(start);
                 Ok(ExprAST::Literal(Literal::String(val)))
             }
             Token::Reference(val, _) => {
                 self.next()?;
+                self.record(start);
                 Ok(ExprAST::Reference(val))
             }
             Token::Function(name, _) => self.parse_function(name),
+            // An infix operator can't start an operand, so `mod(a, b)` calls the
+            // function of the same name.
+            Token::Operator(op, _)
+                if !keyword::is_prefix_op(op) && self.tokenizer.peek()?.is_open_paren() =>
+            {
+                self.parse_function(op)
+            }
             Token::Operator(op, _) => self.parse_unary(op),
             Token::Delim(ty, _) => self.parse_delim(ty),
             Token::EOF => Err(Error::UnexpectedEOF(0)),
@@ -470,20 +912,46 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse_stmt(&mut self) -> Result<ExprAST<'a>> {
+        Ok(self.parse_stmt_with_comments()?.0)
+    }
+
+    /// Like `parse_stmt`, but also returns, for each statement in order, the
+    /// comments written between the previous statement and it or inside it.
+    /// Comments after the last statement go to the last statement. Comments are
+    /// only kept by parsers created with `with_comments`.
+    pub fn parse_stmt_with_comments(&mut self) -> Result<(ExprAST<'a>, Vec<Vec<&'a str>>)> {
         let mut ans = Vec::new();
+        let mut comments = Vec::new();
+        let mut start = None;
         loop {
+            // Empty statements, like the one after a trailing semicolon, are skipped.
+            while self.cur_tok().is_semicolon() {
+                self.next()?;
+            }
             if self.is_eof() {
                 break;
             }
+            let mut before = self.tokenizer.take_comments();
+            start.get_or_insert(self.start());
             ans.push(self.parse_expression()?);
-            if self.cur_tok().is_semicolon() {
-                self.next()?;
-            }
+            // The token after the statement has already been read, so this also
+            // takes the comments between the statement and its semicolon.
+            before.extend(self.tokenizer.take_comments());
+            comments.push(before);
+        }
+        if let Some(last) = comments.last_mut() {
+            last.extend(self.tokenizer.take_comments());
         }
         if ans.len() == 1 {
-            return Ok(ans[0].clone());
+            return Ok((ans[0].clone(), comments));
+        }
+        // The statement list ends with its last statement, not with a trailing
+        // semicolon.
+        if let (Some(start), Some(spans)) = (start, self.spans.as_mut()) {
+            let end = spans.last().map_or(start, |span| span.1);
+            spans.push(Span(start, end));
         }
-        Ok(ExprAST::Stmt(ans))
+        Ok((ExprAST::Stmt(ans), comments))
     }
 
     pub fn parse_expression(&mut self) -> Result<ExprAST<'a>> {
@@ -491,11 +959,38 @@ impl<'a> Parser<'a> {
         self.parse_op(0, lhs)
     }
 
+    /// Parses one expression from the start of the input and returns it with the
+    /// byte offset where the text after it begins, so the caller can go on with
+    /// the surrounding text, e.g. `"1 + 2 rest"` stops at the offset of `rest`.
+    /// The token following the expression is read ahead, so it must still be
+    /// valid input for the tokenizer.
+    pub fn parse_expression_with_end(&mut self) -> Result<(ExprAST<'a>, usize)> {
+        let ast = self.parse_expression()?;
+        let end = match self.tokenizer.cur_token.span() {
+            Some(span) => span.0,
+            None => self.tokenizer.current(),
+        };
+        Ok((ast, end))
+    }
+
     fn parse_primary(&mut self) -> Result<ExprAST<'a>> {
-        let lhs = self.parse_token()?;
+        self.nested(Self::parse_operand)
+    }
+
+    fn parse_operand(&mut self) -> Result<ExprAST<'a>> {
+        let mut lhs = self.parse_token()?;
+        let start = self.last_start();
+        while self.cur_tok().is_open_bracket() {
+            self.next()?;
+            let index = self.parse_expression()?;
+            self.expect("]")?;
+            self.record(start);
+            lhs = ExprAST::Index(Box::new(lhs), Box::new(index));
+        }
         if self.tokenizer.cur_token.is_postfix_op_token() {
             let op = self.tokenizer.cur_token.string();
             self.next()?;
+            self.record(start);
             return Ok(ExprAST::Postfix(Box::new(lhs), op.to_string()));
         }
         Ok(lhs)
@@ -503,6 +998,8 @@ impl<'a> Parser<'a> {
 
     fn parse_op(&mut self, exec_prec: i32, mut lhs: ExprAST<'a>) -> Result<ExprAST<'a>> {
         let mut is_not = false;
+        // Each operator folded into `lhs` nests it one level deeper.
+        let mut chain = 0;
         loop {
             if !self.tokenizer.cur_token.is_op_token() {
                 return Ok(lhs);
@@ -515,12 +1012,16 @@ impl<'a> Parser<'a> {
                 }
                 continue;
             }
+            let start = self.last_start();
             if self.tokenizer.cur_token.is_question_mark() {
                 self.next()?;
-                let a = self.parse_expression()?;
+                self.check_depth(self.depth + chain + 1)?;
+                let a = self.nested(|p| p.parse_expression())?;
                 self.expect(":")?;
-                let b = self.parse_expression()?;
-                return Ok(ExprAST::Ternary(Box::new(lhs), Box::new(a), Box::new(b)));
+                let b = self.nested(|p| p.parse_expression())?;
+                let ternary = ExprAST::Ternary(Box::new(lhs), Box::new(a), Box::new(b));
+                self.record(start);
+                return Ok(ternary);
             }
             let (l_bp, r_bp) = self.get_token_precidence();
             if l_bp < exec_prec {
@@ -535,13 +1036,18 @@ impl<'a> Parser<'a> {
 
             let (cur_l_bp, _) = self.get_token_precidence();
             if self.tokenizer.cur_token.is_binop_token() && r_bp < cur_l_bp {
-                rhs = self.parse_op(r_bp, rhs)?;
+                rhs = self.nested(|p| p.parse_op(r_bp, rhs))?;
             }
+            self.record(start);
             lhs = ExprAST::Binary(op, Box::new(lhs), Box::new(rhs));
+            chain += 1;
             if is_not {
+                self.record(start);
                 lhs = ExprAST::Unary("not", Box::new(lhs));
                 is_not = false;
+                chain += 1;
             }
+            self.check_depth(self.depth + chain)?;
         }
     }
 
@@ -563,16 +1069,23 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_open_paren(&mut self) -> Result<ExprAST<'a>> {
+        let start = self.start();
         self.next()?;
         let expr = self.parse_expression()?;
         if !self.tokenizer.cur_token.is_close_paren() {
             return Err(Error::NoCloseDelim);
         }
         self.next()?;
+        // Widen the span of the parenthesized node to the parentheses.
+        if let Some(spans) = self.spans.as_mut() {
+            spans.pop();
+        }
+        self.record(start);
         Ok(expr)
     }
 
     fn parse_open_bracket(&mut self) -> Result<ExprAST<'a>> {
+        let start = self.start();
         self.next()?;
         let mut exprs = Vec::new();
         loop {
@@ -585,10 +1098,12 @@ impl<'a> Parser<'a> {
             }
         }
         self.expect("]")?;
+        self.record(start);
         Ok(ExprAST::List(exprs))
     }
 
     fn parse_open_brace(&mut self) -> Result<ExprAST<'a>> {
+        let start = self.start();
         self.next()?;
         let mut m = Vec::new();
         loop {
@@ -604,35 +1119,44 @@ impl<'a> Parser<'a> {
             }
         }
         self.expect("}")?;
+        self.record(start);
         Ok(ExprAST::Map(m))
     }
 
     fn parse_unary(&mut self, op: &'a str) -> Result<ExprAST<'a>> {
+        let start = self.start();
         self.next()?;
-        Ok(ExprAST::Unary(op, Box::new(self.parse_primary()?)))
+        let rhs = self.parse_primary()?;
+        self.record(start);
+        Ok(ExprAST::Unary(op, Box::new(rhs)))
     }
 
     fn parse_function(&mut self, name: &'a str) -> Result<ExprAST<'a>> {
+        let start = self.start();
         self.next()?;
         self.expect("(")?;
         let mut ans = Vec::new();
         if self.cur_tok().is_close_paren() {
             self.next()?;
+            self.record(start);
             return Ok(ExprAST::Function(name, ans));
         }
         let has_right_paren;
         loop {
             ans.push(self.parse_expression()?);
+            if !self.cur_tok().is_close_paren() {
+                self.expect(",")?;
+            }
             if self.cur_tok().is_close_paren() {
                 has_right_paren = true;
                 self.next()?;
                 break;
             }
-            self.expect(",")?;
         }
         if !has_right_paren {
             return Err(Error::NoCloseDelim);
         }
+        self.record(start);
         Ok(ExprAST::Function(name, ans))
     }
 }
@@ -640,7 +1164,7 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use crate::init::init;
-    use crate::parser::{ExprAST, Literal, Parser};
+    use crate::parser::{Expr, ExprAST, Literal, Parser};
     use crate::value::Value;
     use rstest::rstest;
     use rust_decimal::prelude::*;
@@ -652,6 +1176,28 @@ mod tests {
     #[case("\n haha", ExprAST::Reference("haha"))]
     #[case("'haha  '", ExprAST::Literal(Literal::String("haha  ")))]
     #[case("!a", ExprAST::Unary("!", Box::new(ExprAST::Reference("a"))))]
+    #[case(
+        "a ?? b",
+        ExprAST::Binary(
+            "??",
+            Box::new(ExprAST::Reference("a")),
+            Box::new(ExprAST::Reference("b"))
+        )
+    )]
+    #[case(
+        "a ? b : c",
+        ExprAST::Ternary(
+            Box::new(ExprAST::Reference("a")),
+            Box::new(ExprAST::Reference("b")),
+            Box::new(ExprAST::Reference("c")),
+        )
+    )]
+    #[case(" null", ExprAST::None)]
+    #[case("nil ", ExprAST::None)]
+    #[case(
+        "x == null",
+        ExprAST::Binary("==", Box::new(ExprAST::Reference("x")), Box::new(ExprAST::None))
+    )]
     fn test_parse_expression_simple(#[case] input: &str, #[case] output: ExprAST) {
         init();
         let parser = Parser::new(input);
@@ -757,6 +1303,36 @@ mod tests {
         assert_eq!(expr_ast.unwrap(), output);
     }
 
+    #[rstest]
+    #[case("[1, a,]", ExprAST::List(vec![
+        ExprAST::Literal(Literal::Number(Decimal::from_i32(1).unwrap_or_default())),
+        ExprAST::Reference("a"),
+    ]))]
+    #[case("{'a': 1, b: 2,}", ExprAST::Map(vec![
+        (
+            ExprAST::Literal(Literal::String("a")),
+            ExprAST::Literal(Literal::Number(Decimal::from_i32(1).unwrap_or_default())),
+        ),
+        (
+            ExprAST::Reference("b"),
+            ExprAST::Literal(Literal::Number(Decimal::from_i32(2).unwrap_or_default())),
+        ),
+    ]))]
+    #[case("f(a, 'b',)", ExprAST::Function("f", vec![
+        ExprAST::Reference("a"),
+        ExprAST::Literal(Literal::String("b")),
+    ]))]
+    #[case("f([1,],)", ExprAST::Function("f", vec![
+        ExprAST::List(vec![
+            ExprAST::Literal(Literal::Number(Decimal::from_i32(1).unwrap_or_default())),
+        ]),
+    ]))]
+    fn test_parse_expression_trailing_comma(#[case] input: &str, #[case] output: ExprAST) {
+        init();
+        let expr_ast = Parser::new(input).unwrap().parse_expression();
+        assert_eq!(expr_ast.unwrap(), output);
+    }
+
     #[rstest]
     #[case(" true ? 234:'haha'", ExprAST::Ternary(
         Box::new(ExprAST::Literal(Literal::Bool(true))),
@@ -784,6 +1360,11 @@ mod tests {
     #[case(" (")]
     #[case("a(")]
     #[case("a(,)")]
+    #[case("[,]")]
+    #[case("{,}")]
+    #[case("[1,,]")]
+    #[case("a(1,,)")]
+    #[case("a(1,")]
     #[case("a(2,true,")]
     #[case("true ?")]
     #[case("true ? haha :")]
@@ -800,6 +1381,16 @@ mod tests {
     #[case("+true")]
     #[case("- 'hha'")]
     #[case("! 'haha'")]
+    #[case("~true")]
+    #[case("1 mod 0")]
+    #[case("1 // 0")]
+    #[case("if(1, 2, 3)")]
+    #[case("if(true, 1)")]
+    #[case("if(false, 1, 2, 3)")]
+    #[case("~1.5")]
+    #[case("1 < '2'")]
+    #[case("'abc' < 'abd'")]
+    #[case("true >= false")]
     #[case("fasle ++")]
     #[case("'haha' --")]
     fn test_execute_error(#[case] input: &str) {
@@ -883,6 +1474,21 @@ mod tests {
             )
             ))
     ))]
+    #[case("a = 1;", ExprAST::Binary(
+        "=",
+        Box::new(ExprAST::Reference("a")),
+        Box::new(ExprAST::Literal(Literal::Number(1.into())))
+    ))]
+    #[case("a = 1;; b;", ExprAST::Stmt(vec![
+        ExprAST::Binary(
+            "=",
+            Box::new(ExprAST::Reference("a")),
+            Box::new(ExprAST::Literal(Literal::Number(1.into())))
+        ),
+        ExprAST::Reference("b"),
+    ]))]
+    #[case(";; a", ExprAST::Reference("a"))]
+    #[case(" ; ", ExprAST::Stmt(vec![]))]
     fn test_parse_chain_expression(#[case] input: &str, #[case] output: ExprAST) {
         init();
         let parser = Parser::new(input);
@@ -903,11 +1509,41 @@ mod tests {
         assert!(expr_ast.is_err());
     }
 
+    #[rstest]
+    #[case("a", vec![vec![]])]
+    #[case("# only\na", vec![vec!["only"]])]
+    #[case("#one\n  #  two  \na; b", vec![vec!["one", "two"], vec![]])]
+    #[case("a;\n# about b\nb;\n# trailing", vec![vec![], vec!["about b", "trailing"]])]
+    #[case("a + # inside\n b; c", vec![vec!["inside"], vec![]])]
+    #[case("a # after a\n; # before b\nb", vec![vec!["after a"], vec!["before b"]])]
+    #[case("# nothing else", vec![])]
+    #[case("'#not' + `# either`", vec![vec![]])]
+    fn test_parse_comments(#[case] input: &str, #[case] comments: Vec<Vec<&str>>) {
+        init();
+        let (ast, ans) = Parser::with_comments(input)
+            .unwrap()
+            .parse_stmt_with_comments()
+            .unwrap();
+        assert_eq!(ans, comments);
+        assert_eq!(ast, Parser::new(input).unwrap().parse_stmt().unwrap());
+        let (_, ans) = Parser::new(input)
+            .unwrap()
+            .parse_stmt_with_comments()
+            .unwrap();
+        assert!(ans.iter().all(|c| c.is_empty()));
+    }
+
+    use crate::context::Context;
     use crate::create_context;
+    use crate::error::Error;
     use crate::function::InnerFunctionManager;
+    use crate::operator::{InfixOpAssociativity, InfixOpManager, InfixOpType};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::Arc;
     #[rstest]
     #[case("2", 2.into())]
+    #[case("a = 1;; b = 2; a + b;", 3.into())]
     #[case("'haha'", "haha".into())]
     #[case("true", true.into())]
     #[case("  False", false.into())]
@@ -933,6 +1569,13 @@ mod tests {
     #[case("d>>=2;d", (3>>2).into())]
     #[case("'hahhadf' beginWith \"hahha\"", true.into())]
     #[case("'hahhadf' endWith \"hahha\"", false.into())]
+    #[case("'Hello' beginWith 'hello'", false.into())]
+    #[case("'Hello' beginWithI 'hello'", true.into())]
+    #[case("'hello' beginWithI 'HEL'", true.into())]
+    #[case("'Hello' beginWithI 'lo'", false.into())]
+    #[case("'Hello World' endWith 'world'", false.into())]
+    #[case("'Hello World' endWithI 'world'", true.into())]
+    #[case("'Hello' endWithI 'HELL'", false.into())]
     #[case("true in [2, true, 'haha']", true.into())]
     #[case("-5*10", (-50).into())]
     #[case("AND[1>2,true]", false.into())]
@@ -962,7 +1605,7 @@ mod tests {
         vec![false.into(),6.into()]
     ))]
     #[case("[2>3,1+5, true]", 
-        vec![false.into(),6.into(), true.into()].into()
+        Value::List(vec![false.into(),6.into(), true.into()])
     )]
     #[case("{'haha':2, 1+2:2>3}", Value::Map(
         vec![("haha".into(),2.into()),(3.into(),false.into())]
@@ -979,6 +1622,53 @@ mod tests {
     #[case("'a' not in ['a']", false.into())]
     #[case("2 not in ['a', false, true, 1+2]", true.into())]
     #[case("3 not in ['a', false, true, 1+2] || 3>=2", true.into())]
+    #[case("0xFF & 0b1010", 10.into())]
+    #[case("0b0101 | 0x0A", 15.into())]
+    #[case("0x10 + 1", 17.into())]
+    #[case("if(true, 1, undefined_func())", 1.into())]
+    #[case("if(false, undefined_func(), 'b')", "b".into())]
+    #[case("if(d > 2, 'big', 'small')", "big".into())]
+    #[case("if(true, x = 1, x = 2); x", 1.into())]
+    #[case("if(false, x = 1, x = 2); x", 2.into())]
+    #[case("x ??= 5; x", 5.into())]
+    #[case("d ??= 5; d", 3.into())]
+    #[case("x ??= 5; x ??= 6; x", 5.into())]
+    #[case("-7 % 3", (-1).into())]
+    #[case("+5 + +3", 8.into())]
+    #[case("-5 - -3", (-2).into())]
+    #[case("5 * -3 + +2", (-13).into())]
+    #[case("[+1, -2]", Value::List(vec![1.into(), (-2).into()]))]
+    #[case("-7 mod 3", 2.into())]
+    #[case("7 // 2", 3.into())]
+    #[case("-7 // 2", (-4).into())]
+    #[case("7.5 // -2", (-4).into())]
+    #[case("6 // 3 * 2", 4.into())]
+    #[case("1 + 9 // 4", 3.into())]
+    #[case("7 mod -3", (-2).into())]
+    #[case("-7 mod -3", (-1).into())]
+    #[case("7 mod 3", 1.into())]
+    #[case("6 mod -3", 0.into())]
+    #[case("-7.5 mod 2", Value::from(0.5))]
+    #[case("1 + 7 mod 3 * 2", 3.into())]
+    #[case("x ?? 'default'", "default".into())]
+    #[case("d ?? 1", 3.into())]
+    #[case("x ?? y ?? 2", 2.into())]
+    #[case("d ?? undefined_func()", 3.into())]
+    #[case("x ?? 1 + 2", 3.into())]
+    #[case("(x ?? d) > 2 ? 'a' : 'b'", "a".into())]
+    #[case("x ?? d > 2 ? 'a' : 'b'", "a".into())]
+    #[case("x ??= d ?? 1; x", 3.into())]
+    #[case("null", Value::None)]
+    #[case("x == null", true.into())]
+    #[case("d == nil", false.into())]
+    #[case("d != null", true.into())]
+    #[case("[null, 1]", Value::List(vec![Value::None, 1.into()]))]
+    #[case("~0", (-1).into())]
+    #[case("~5", (-6).into())]
+    #[case("~~5", 5.into())]
+    #[case("~0xF0 & 0xFF", 15.into())]
+    #[case("# comment\n1 + # another\n2 #", 3.into())]
+    #[case("'#a' beginWith `#`", true.into())]
     fn test_exec(#[case] input: &str, #[case] output: Value) {
         init();
         let mut ctx = create_context!(
@@ -1001,6 +1691,250 @@ mod tests {
         ast.clone().describe();
     }
 
+    #[rstest]
+    #[case("[1,2,3][0]", 1.into())]
+    #[case("[1,2,3][-1]", 3.into())]
+    #[case("[1,2,3][-3]", 1.into())]
+    #[case("[[1,2],[3,4]][1][0]", 3.into())]
+    #[case("l[1 + 1] * 2", 6.into())]
+    #[case("-l[0]", (-1).into())]
+    #[case("(l)[2]", 3.into())]
+    #[case("'héllo'[1]", "é".into())]
+    #[case("'abc'[-1]", "c".into())]
+    #[case("{'a': 1, 2: 'b'}['a']", 1.into())]
+    #[case("{'a': 1, 2: 'b'}[1 + 1]", "b".into())]
+    #[case("{'a': 1}['b']", Value::None)]
+    fn test_exec_index(#[case] input: &str, #[case] output: Value) {
+        init();
+        let mut ctx = Context::from_variables([(
+            "l".to_string(),
+            Value::List(vec![1.into(), 2.into(), 3.into()]),
+        )]);
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec(&mut ctx).unwrap(), output);
+    }
+
+    #[rstest]
+    #[case("[1,2,3][5]", Some(5))]
+    #[case("[1,2,3][3]", Some(3))]
+    #[case("[1,2,3][-4]", Some(-4))]
+    #[case("[][0]", Some(0))]
+    #[case("''[0]", Some(0))]
+    #[case("[1,2,3][0.5]", None)]
+    #[case("[1,2,3]['a']", None)]
+    #[case("5[0]", None)]
+    fn test_exec_index_error(#[case] input: &str, #[case] out_of_range: Option<i64>) {
+        init();
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        let err = ast.exec(&mut Context::new()).unwrap_err();
+        match out_of_range {
+            Some(index) => assert!(matches!(err, Error::IndexOutOfRange(i) if i == index)),
+            None => assert!(!matches!(err, Error::IndexOutOfRange(_))),
+        }
+    }
+
+    #[rstest]
+    #[case("false && probe()", false.into(), false)]
+    #[case("true || probe()", true.into(), false)]
+    #[case("true && probe()", true.into(), true)]
+    #[case("false || probe()", true.into(), true)]
+    #[case("true || missing_ref", true.into(), false)]
+    #[case("1 > 2 && probe() || true", true.into(), false)]
+    fn test_exec_short_circuit(#[case] input: &str, #[case] output: Value, #[case] ran: bool) {
+        init();
+        let called = Arc::new(AtomicBool::new(false));
+        let flag = called.clone();
+        let mut ctx = Context::new();
+        ctx.set_func(
+            "probe",
+            Arc::new(move |_| {
+                flag.store(true, Ordering::SeqCst);
+                Ok(true.into())
+            }),
+        );
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec(&mut ctx).unwrap(), output);
+        assert_eq!(called.load(Ordering::SeqCst), ran);
+    }
+
+    #[rstest]
+    #[case("a + b * c")]
+    #[case("[f(a + 1, a + 1), {a + 1: -(a + 1)}, a + 1 > 2 ? a + 1 : b--]")]
+    #[case("x = a + 1; x = a + 1; x")]
+    fn test_describe_cached(#[case] input: &str) {
+        init();
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        let mut cache = HashMap::new();
+        assert_eq!(ast.describe_cached(&mut cache), ast.describe());
+        assert_eq!(cache.get(&ast), Some(&ast.describe()));
+        assert_eq!(ast.describe_cached(&mut cache), ast.describe());
+    }
+
+    #[rstest]
+    #[case("big_list", Value::List(vec![1.into(), 2.into()]))]
+    #[case("d", 3.into())]
+    #[case("f", 4.into())]
+    #[case("missing", Value::None)]
+    #[case("d + 1", 4.into())]
+    fn test_exec_with(#[case] input: &str, #[case] output: Value) {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_variable("big_list", Value::List(vec![1.into(), 2.into()]));
+        ctx.set_variable("d", 3.into());
+        ctx.set_func("f", Arc::new(|_| Ok(4.into())));
+        let ast = Parser::new(input).unwrap().parse_stmt().unwrap();
+        assert_eq!(ast.exec_with(&mut ctx, |v| v.clone()).unwrap(), output);
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(16)]
+    #[case(64)]
+    fn test_exec_nested_list(#[case] depth: usize) {
+        init();
+        let input = "[".repeat(depth) + "1+1, {'k': [d, 'v']}" + &"]".repeat(depth);
+        let mut output = Value::List(vec![
+            Value::from(2),
+            Value::Map(vec![(
+                Value::from("k"),
+                Value::List(vec![Value::from(3), Value::from("v")]),
+            )]),
+        ]);
+        for _ in 1..depth {
+            output = Value::List(vec![output]);
+        }
+        let ast = Parser::new(&input).unwrap().parse_stmt().unwrap();
+        let mut ctx = Context::new();
+        ctx.set_variable("d", Value::from(3));
+        assert_eq!(ast.exec(&mut ctx).unwrap(), output);
+        assert_eq!(ast.to_owned_ast().exec(&mut ctx).unwrap(), output);
+        assert_eq!(ast.exec(&mut ctx).unwrap(), output);
+    }
+
+    #[rstest]
+    #[case(Expr::binary("+", Expr::reference("a"), Expr::number(3)), "a + 3", 5.into())]
+    #[case(
+        Expr::ternary(
+            Expr::binary(">", Expr::reference("a"), Expr::number(1)),
+            Expr::function("max", vec![Expr::list(vec![Expr::number(4), Expr::reference("a")])]),
+            Expr::string("small"),
+        ),
+        "a > 1 ? max([4,a]) : \"small\"",
+        4.into()
+    )]
+    #[case(
+        Expr::index(Expr::map(vec![(Expr::string("k"), Expr::bool(true))]), Expr::string("k")),
+        "{\"k\":true}[\"k\"]",
+        true.into()
+    )]
+    #[case(Expr::unary("-", Expr::postfix(Expr::reference("a"), "++")), "- a ++", (-3).into())]
+    fn test_build_ast(#[case] ast: Expr<String>, #[case] expr: &str, #[case] output: Value) {
+        init();
+        let mut ctx = Context::new();
+        ctx.set_variable("a", Value::from(2));
+        assert_eq!(ast.expr(), expr);
+        assert_eq!(ast.exec(&mut ctx).unwrap(), output);
+        assert_eq!(
+            Parser::new(expr)
+                .unwrap()
+                .parse_stmt()
+                .unwrap()
+                .to_owned_ast(),
+            ast
+        );
+    }
+
+    #[rstest]
+    #[case("(".repeat(10_000) + "1" + &")".repeat(10_000))]
+    #[case("[".repeat(10_000))]
+    #[case("!".repeat(10_000) + "true")]
+    #[case("a = ".repeat(10_000) + "1")]
+    #[case("f(".repeat(10_000))]
+    #[case("x[".repeat(10_000))]
+    #[case("1".to_string() + &" + 1".repeat(10_000))]
+    #[case("a ? b : ".repeat(10_000) + "c")]
+    #[case("a".to_string() + &" not in b".repeat(10_000))]
+    fn test_parse_too_deep(#[case] input: String) {
+        init();
+        let ans = Parser::new(&input).unwrap().parse_stmt();
+        assert!(matches!(ans, Err(Error::ExpressionTooDeep(128))));
+        let err = crate::execute(&input, Context::new()).unwrap_err();
+        assert!(matches!(err, Error::ExpressionTooDeep(128)));
+    }
+
+    #[test]
+    fn test_parse_spans() {
+        init();
+        let input = "f(a, 1) + -(b);\nx[0]++";
+        let mut parser = Parser::with_spans(input).unwrap();
+        parser.parse_stmt().unwrap();
+        let spans: Vec<&str> = parser
+            .take_spans()
+            .into_iter()
+            .map(|span| &input[span.0..span.1])
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                "a",
+                "1",
+                "f(a, 1)",
+                "(b)",
+                "-(b)",
+                "f(a, 1) + -(b)",
+                "x",
+                "0",
+                "x[0]",
+                "x[0]++",
+                "f(a, 1) + -(b);\nx[0]++",
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("1 + 2 rest", "1 + 2", "rest")]
+    #[case("f(a) ; b", "f(a)", "; b")]
+    #[case("[1, 2]", "[1,2]", "")]
+    #[case("x ? 1 : 2   ", "x ? 1 : 2", "")]
+    fn test_parse_expression_with_end(#[case] input: &str, #[case] expr: &str, #[case] rest: &str) {
+        init();
+        let (ast, end) = Parser::new(input)
+            .unwrap()
+            .parse_expression_with_end()
+            .unwrap();
+        assert_eq!(ast.expr(), expr);
+        assert_eq!(&input[end..], rest);
+    }
+
+    #[rstest]
+    #[case("(".repeat(128) + "1" + &")".repeat(128), 128, true)]
+    #[case("(".repeat(128) + "1" + &")".repeat(128), 127, false)]
+    #[case("1".to_string() + &" + 1".repeat(256), 256, true)]
+    #[case("1".to_string() + &" + 1".repeat(257), 256, false)]
+    #[case("(1)".to_string(), 1, true)]
+    #[case("(1)".to_string(), 0, false)]
+    #[case("1 + 2".to_string(), 1, true)]
+    #[case("1 + 2".to_string(), 0, false)]
+    #[case("-(1 + 2 + 3)".to_string(), 4, true)]
+    #[case("-(1 + 2 + 3)".to_string(), 3, false)]
+    #[case("1 + 2 * 3 - 4 / 5".to_string(), 2, true)]
+    #[case("1 + 2 * 3 - 4 / 5".to_string(), 1, false)]
+    #[case("true ? 1 : 2".to_string(), 1, true)]
+    #[case("true ? 1 : 2".to_string(), 0, false)]
+    fn test_parse_max_depth(#[case] input: String, #[case] max_depth: usize, #[case] ok: bool) {
+        init();
+        let mut parser = Parser::new(&input).unwrap();
+        parser.set_max_depth(max_depth);
+        let ans = parser.parse_stmt();
+        if ok {
+            let ast = ans.unwrap();
+            assert!(ast.exec(&mut Context::new()).is_ok());
+            ast.describe();
+        } else {
+            assert!(matches!(ans, Err(Error::ExpressionTooDeep(d)) if d == max_depth));
+        }
+    }
+
     #[rstest]
     #[case("5", "5")]
     #[case(" true ", "true")]
@@ -1031,6 +1965,18 @@ mod tests {
     #[case("a()++ * 2-7", "a() ++ * 2 - 7")]
     #[case("2++ + 3", "2 ++ + 3")]
     #[case("a()++ * 2-7", "a() ++ * 2 - 7")]
+    #[case("a - (b - c)", "a - (b - c)")]
+    #[case("a - b - c", "a - b - c")]
+    #[case("a = (b = c)", "a = b = c")]
+    #[case("(a = b) = c", "(a = b) = c")]
+    #[case("-(2+3)", "- (2 + 3)")]
+    #[case("(a+b)++", "(a + b) ++")]
+    #[case("(-a)++", "(- a) ++")]
+    #[case("(true ? 1 : 2) + 3", "(true ? 1 : 2) + 3")]
+    #[case("x == nil", "x == null")]
+    #[case("2 not in [2]", "not (2 in [2])")]
+    #[case("a[0] + b [ 1 ][-1]", "a[0] + b[1][- 1]")]
+    #[case("(a+b)[0]", "(a + b)[0]")]
     fn test_expression_expr(#[case] input: &str, #[case] output: &str) {
         init();
         let parser = Parser::new(input);
@@ -1039,4 +1985,29 @@ mod tests {
         assert!(expr_ast.is_ok());
         assert_eq!(expr_ast.unwrap().expr(), output);
     }
+
+    #[rstest]
+    #[case("a rt_minus -5")]
+    #[case("a rt_minus - 5 rt_minus -b")]
+    #[case("-a rt_minus (b rt_minus c)")]
+    #[case("a rt_minus (2 + 3) * 4")]
+    #[case("not a && (b || c)")]
+    #[case("-(2+3)*5")]
+    #[case("(a+b)++ - (c-d)--")]
+    #[case("2 not in [2, 3] || a")]
+    #[case("-a[0] rt_minus (b rt_minus c)[d[1]]")]
+    fn test_expression_expr_round_trip(#[case] input: &str) {
+        init();
+        InfixOpManager::new().register(
+            "rt_minus",
+            110,
+            InfixOpType::CALC,
+            InfixOpAssociativity::LEFT,
+            Arc::new(|left, right| Ok(Value::from(left.decimal()? - right.decimal()?))),
+        );
+        let ast = Parser::new(input).unwrap().parse_expression().unwrap();
+        let output = ast.expr();
+        let reparsed = Parser::new(&output).unwrap().parse_expression().unwrap();
+        assert_eq!(reparsed, ast);
+    }
 }
@@ -0,0 +1,42 @@
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The storage of a registry, kept in a static cell for the global one.
+pub type Store<K, V> = Arc<Mutex<HashMap<K, V>>>;
+
+/// A map from keys to registered callbacks, shared by the managers that
+/// customize how expressions are rendered. Each manager owns the static cell
+/// of its global registry.
+pub struct Registry<K, V> {
+    store: Store<K, V>,
+}
+
+impl<K: Hash + Eq, V: Clone> Registry<K, V> {
+    pub fn new(cell: &'static OnceCell<Store<K, V>>) -> Self {
+        Registry {
+            store: cell.get_or_init(Default::default).clone(),
+        }
+    }
+
+    /// Creates an empty registry that isn't shared with the global one.
+    pub fn local() -> Self {
+        Registry {
+            store: Default::default(),
+        }
+    }
+
+    pub fn set(&self, key: K, value: V) {
+        self.store.lock().insert(key, value);
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.store.lock().get(key).cloned()
+    }
+
+    pub fn clear(&self) {
+        self.store.lock().clear();
+    }
+}
@@ -76,6 +76,7 @@ pub enum Token<'input> {
     Number(Decimal, Span),
     Comma(&'input str, Span),
     Bool(bool, Span),
+    Null(Span),
     String(&'input str, Span),
     Reference(&'input str, Span),
     Function(&'input str, Span),
@@ -117,10 +118,6 @@ impl<'input> Token<'input> {
         check_op(self, "]")
     }
 
-    pub fn is_open_brace(self) -> bool {
-        check_op(self, "{")
-    }
-
     pub fn is_close_brace(self) -> bool {
         check_op(self, "}")
     }
@@ -129,10 +126,6 @@ impl<'input> Token<'input> {
         check_op(self, "?")
     }
 
-    pub fn is_colon(self) -> bool {
-        check_op(self, ":")
-    }
-
     pub fn is_eof(self) -> bool {
         match self {
             Self::EOF => true,
@@ -175,6 +168,23 @@ impl<'input> Token<'input> {
         }
     }
 
+    pub fn span(self) -> Option<Span> {
+        use Token::*;
+        match self {
+            Operator(_, span)
+            | Delim(_, span)
+            | Number(_, span)
+            | Comma(_, span)
+            | Bool(_, span)
+            | Null(span)
+            | String(_, span)
+            | Reference(_, span)
+            | Function(_, span)
+            | Semicolon(_, span) => Some(span),
+            EOF => None,
+        }
+    }
+
     #[cfg(not(tarpaulin_include))]
     pub fn string(self) -> String {
         use Token::*;
@@ -183,6 +193,7 @@ impl<'input> Token<'input> {
             Number(val, _) => val.to_string(),
             Comma(val, _) => val.to_string(),
             Bool(val, _) => val.to_string(),
+            Null(_) => "null".to_string(),
             String(val, _) => val.to_string(),
             Reference(val, _) => val.to_string(),
             Function(val, _) => val.to_string(),
@@ -206,6 +217,7 @@ impl<'input> fmt::Display for Token<'input> {
         use Token::*;
         match self {
             Bool(val, span) => write!(f, "Bool Token: {}, {}", val, span),
+            Null(span) => write!(f, "Null Token: {}", span),
             Comma(val, span) => write!(f, "Comma Token: {}, {}", val, span),
             Number(val, span) => write!(f, "Number Token: {}, {}", val, span),
             Operator(val, span) => write!(f, "Operator Token: {}, {}", val, span),
@@ -256,14 +268,6 @@ mod tests {
         assert_eq!(input.is_open_paren(), output)
     }
 
-    #[rstest]
-    #[case(Token::Delim(DelimTokenType::OpenBrace, Span(0, 0)), true)]
-    #[case(Token::Delim(DelimTokenType::CloseBrace, Span(0, 0)), false)]
-    #[case(Token::Bool(false, Span(0, 0)), false)]
-    fn test_is_open_brace(#[case] input: Token, #[case] output: bool) {
-        assert_eq!(input.is_open_brace(), output)
-    }
-
     #[rstest]
     #[case(Token::Delim(DelimTokenType::OpenBracket, Span(0, 0)), true)]
     #[case(Token::Delim(DelimTokenType::CloseBracket, Span(0, 0)), false)]
@@ -1,6 +1,7 @@
 use crate::define::Result;
 use crate::error::Error;
 use crate::keyword;
+use crate::operator::max_op_len;
 use crate::token::{Span, Token};
 use rust_decimal::prelude::*;
 use std::str;
@@ -12,19 +13,40 @@ pub struct Tokenizer<'a> {
     cur_char: char,
     pub cur_token: Token<'a>,
     pub prev_token: Token<'a>,
+    // The text of the comments skipped since the last `take_comments`, kept only
+    // when the tokenizer was created by `with_comments`.
+    comments: Option<Vec<&'a str>>,
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(input: &str) -> Tokenizer {
         Tokenizer {
-            input: input,
+            input,
             chars: input.char_indices(),
             cur_char: ' ',
             cur_token: Token::EOF,
             prev_token: Token::EOF,
+            comments: None,
         }
     }
 
+    /// Creates a tokenizer that keeps the comments it skips, see `take_comments`.
+    pub fn with_comments(input: &str) -> Tokenizer<'_> {
+        Tokenizer {
+            comments: Some(Vec::new()),
+            ..Tokenizer::new(input)
+        }
+    }
+
+    /// Returns the comments skipped so far, without the leading `#` and surrounding
+    /// whitespace, and clears them.
+    pub fn take_comments(&mut self) -> Vec<&'a str> {
+        self.comments
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
     fn next_one(&mut self) -> Option<(usize, char)> {
         let (cur, cur_char) = self.chars.next()?;
         self.cur_char = cur_char;
@@ -39,13 +61,10 @@ impl<'a> Tokenizer<'a> {
         self.eat_whitespace();
         self.prev_token = self.cur_token;
         self.cur_token = match self.next_one() {
-            Some((
-                start,
-                '+' | '-' | '*' | '/' | '^' | '%' | '&' | '!' | '=' | '?' | ':' | '>' | '<' | '|',
-            )) => self.special_op_token(start),
+            Some((start, ch)) if is_special_op_char(ch) => self.special_op_token(start),
             Some((start, '(' | ')' | '[' | ']' | '{' | '}')) => self.delim_token(start),
             Some((start, _ch @ '0'..='9')) => self.number_token(start),
-            Some((start, '"' | '\'')) => self.string_token(start),
+            Some((start, '"' | '\'' | '`')) => self.string_token(start),
             Some((start, ';')) => self.semicolon_token(start),
             Some((start, ',')) => self.comma_token(start),
             None => Ok(Token::EOF),
@@ -54,17 +73,19 @@ impl<'a> Tokenizer<'a> {
         Ok(self.cur_token)
     }
 
+    // Takes the longest run of operator characters that forms a registered operator,
+    // so `??=` is found even when `??` isn't an operator on its own. The run is cut
+    // at the longest registered operator, which keeps long runs like `!!!!` linear.
     fn special_op_token(&mut self, start: usize) -> Result<Token<'a>> {
-        loop {
-            match self.peek_one() {
-                Some((_, _ch)) => {
-                    if keyword::is_op(&(self.input[start..self.current() + 1].to_string())) {
-                        self.next_one();
-                    } else {
-                        break;
-                    }
-                }
-                None => break,
+        let max_len = max_op_len();
+        let mut tmp = self.clone();
+        while let Some((_, ch)) = tmp.peek_one() {
+            if !is_special_op_char(ch) || tmp.current() - start >= max_len {
+                break;
+            }
+            tmp.next_one();
+            if keyword::is_op(&tmp.input[start..tmp.current()]) {
+                *self = tmp.clone();
             }
         }
         Ok(Token::Operator(
@@ -73,51 +94,50 @@ impl<'a> Tokenizer<'a> {
         ))
     }
 
-    fn other_token(&mut self, _: char, start: usize) -> Result<Token<'a>> {
-        if self.try_parse_op(start) {
-            return self.operator_token(start);
+    fn other_token(&mut self, ch: char, start: usize) -> Result<Token<'a>> {
+        if self.try_parse_op(ch, start) {
+            return self.operator_token(ch, start);
         }
         let (atom, start) = self.parse_var(start);
         if atom == "True" || atom == "true" {
             return self.bool_token(start, true);
         } else if atom == "False" || atom == "false" {
             return self.bool_token(start, false);
+        } else if atom == "null" || atom == "nil" {
+            return Ok(Token::Null(Span(start, self.current())));
         }
         return self.function_or_reference_token(atom, start);
     }
 
-    fn try_parse_op(&self, start: usize) -> bool {
+    fn try_parse_op(&self, ch: char, start: usize) -> bool {
         let mut tmp = self.clone();
-        loop {
-            match tmp.peek_one() {
-                Some((_, ch)) => {
-                    if is_whitespace_char(ch) || is_delim_char(ch) {
-                        break;
-                    }
-                    tmp.next_one();
-                }
-                None => break,
-            }
-        }
+        tmp.eat_op(ch);
         keyword::is_op(&tmp.input[start..tmp.current()])
     }
 
-    fn operator_token(&mut self, start: usize) -> Result<Token<'a>> {
-        loop {
-            match self.peek_one() {
-                Some((_, ch)) => {
-                    if is_whitespace_char(ch) || is_delim_char(ch) {
-                        break;
-                    }
-                    self.next_one();
-                }
-                None => break,
+    fn operator_token(&mut self, ch: char, start: usize) -> Result<Token<'a>> {
+        self.eat_op(ch);
+        Ok(Token::Operator(
+            &self.input[start..self.current()],
+            Span(start, self.current()),
+        ))
+    }
+
+    // A word operator like `not` or a registered `sqrt_of` ends where a reference
+    // would, so `sqrt_of-9` splits the same way as `x-9`. Other operators starting
+    // here run to the next whitespace or delimiter.
+    fn eat_op(&mut self, first: char) {
+        while let Some((_, ch)) = self.peek_one() {
+            let end = if is_param_char(first) {
+                !is_param_char(ch)
+            } else {
+                is_whitespace_char(ch) || is_delim_char(ch)
+            };
+            if end {
+                break;
             }
+            self.next_one();
         }
-        return Ok(Token::Operator(
-            self.input[start..self.current()].into(),
-            Span(start, self.current()),
-        ));
     }
 
     fn parse_var(&mut self, start: usize) -> (&'a str, usize) {
@@ -136,6 +156,19 @@ impl<'a> Tokenizer<'a> {
         (self.input[start..self.current()].into(), start)
     }
 
+    /// Maps a byte offset in the input, e.g. from a `Span`, to its 1-based line and column.
+    /// Offsets inside a multi-byte character map to that character.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let mut offset = offset.min(self.input.len());
+        while !self.input.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let prefix = &self.input[..offset];
+        let line = prefix.matches('\n').count() + 1;
+        let col = prefix.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        (line, col)
+    }
+
     pub fn peek(&self) -> Result<Token> {
         self.clone().next()
     }
@@ -188,6 +221,11 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn number_token(&mut self, start: usize) -> Result<Token<'a>> {
+        if self.cur_char == '0' {
+            if let Some((_, 'x' | 'X' | 'b' | 'B')) = self.peek_one() {
+                return self.radix_number_token(start);
+            }
+        }
         loop {
             match self.peek_one() {
                 Some((_, ch)) => {
@@ -203,11 +241,31 @@ impl<'a> Tokenizer<'a> {
                 None => break,
             }
         }
-        match Decimal::from_str(&self.input[start..self.current()]) {
-            Ok(val) => Ok(Token::Number(val, Span(start, self.current()))),
-            Err(_) => Err(Error::InvalidNumber(
-                self.input[start..self.current()].to_string(),
-            )),
+        let literal = &self.input[start..self.current()];
+        match strip_digit_separators(literal, 10).map(|s| Decimal::from_str(&s)) {
+            Some(Ok(val)) => Ok(Token::Number(val, Span(start, self.current()))),
+            _ => Err(Error::InvalidNumber(literal.to_string())),
+        }
+    }
+
+    fn radix_number_token(&mut self, start: usize) -> Result<Token<'a>> {
+        let radix = match self.next_one() {
+            Some((_, 'x' | 'X')) => 16,
+            _ => 2,
+        };
+        while let Some((_, ch)) = self.peek_one() {
+            if !ch.is_ascii_alphanumeric() && ch != '_' {
+                break;
+            }
+            self.next_one();
+        }
+        let literal = &self.input[start..self.current()];
+        let value = strip_digit_separators(&literal[2..], radix)
+            .and_then(|digits| i128::from_str_radix(&digits, radix).ok())
+            .and_then(Decimal::from_i128);
+        match value {
+            Some(val) => Ok(Token::Number(val, Span(start, self.current()))),
+            None => Err(Error::InvalidNumber(literal.to_string())),
         }
     }
 
@@ -248,9 +306,11 @@ impl<'a> Tokenizer<'a> {
 
     fn eat_whitespace(&mut self) -> Option<()> {
         loop {
-            let (_, ch) = self.peek_one()?;
+            let (start, ch) = self.peek_one()?;
             if is_whitespace_char(ch) {
                 self.next_one();
+            } else if ch == '#' {
+                self.eat_comment(start);
             } else {
                 break;
             }
@@ -258,7 +318,21 @@ impl<'a> Tokenizer<'a> {
         Some(())
     }
 
-    fn current(&self) -> usize {
+    // A comment starts with `#` and runs to the end of the line.
+    fn eat_comment(&mut self, start: usize) {
+        while let Some((_, ch)) = self.peek_one() {
+            if ch == '\n' {
+                break;
+            }
+            self.next_one();
+        }
+        let text = self.input[start + 1..self.current()].trim();
+        if let Some(comments) = &mut self.comments {
+            comments.push(text);
+        }
+    }
+
+    pub(crate) fn current(&self) -> usize {
         self.chars
             .clone()
             .next()
@@ -268,7 +342,31 @@ impl<'a> Tokenizer<'a> {
 }
 
 fn is_digit_char(ch: char) -> bool {
-    return '0' <= ch && ch <= '9' || ch == '.' || ch == '-' || ch == 'e' || ch == 'E' || ch == '+';
+    ch.is_ascii_digit() || matches!(ch, '.' | '-' | 'e' | 'E' | '+' | '_')
+}
+
+// Underscores are only allowed as separators between two digits, e.g. 1_000_000.
+fn strip_digit_separators(literal: &str, radix: u32) -> Option<String> {
+    let chars: Vec<char> = literal.chars().collect();
+    for i in 0..chars.len() {
+        if chars[i] != '_' {
+            continue;
+        }
+        if i == 0 || i + 1 == chars.len() {
+            return None;
+        }
+        if !chars[i - 1].is_digit(radix) || !chars[i + 1].is_digit(radix) {
+            return None;
+        }
+    }
+    Some(literal.replace('_', ""))
+}
+
+fn is_special_op_char(ch: char) -> bool {
+    matches!(
+        ch,
+        '+' | '-' | '*' | '/' | '^' | '%' | '&' | '!' | '=' | '?' | ':' | '>' | '<' | '|' | '~'
+    )
 }
 
 fn is_whitespace_char(ch: char) -> bool {
@@ -318,6 +416,13 @@ mod tests {
     #[rstest]
     #[case(" 1234 ", "1234", 1, 5)]
     #[case(" 5.678 ", "5.678", 1, 6)]
+    #[case(" 1_000 ", "1000", 1, 6)]
+    #[case("1_000.50", "1000.50", 0, 8)]
+    #[case("1_000_000", "1000000", 0, 9)]
+    #[case(" 0xff ", "255", 1, 5)]
+    #[case("0XFF_FF", "65535", 0, 7)]
+    #[case("0b1010", "10", 0, 6)]
+    #[case("0B1111_0000", "240", 0, 11)]
     // #[case(" 10e-3 ", "10e-3", 1, 6)]
     // #[case(" 10e03 ", "10e03", 1, 6)]
     // #[case(" 2e+3 ", "2e+3", 1, 5)]
@@ -365,6 +470,16 @@ mod tests {
     #[case(" +=", Operator("+=", Span(1, 3)))]
     #[case(" +=+", Operator("+=", Span(1, 3)))]
     #[case(" +=9", Operator("+=", Span(1, 3)))]
+    #[case(" null ", Null(Span(1, 5)))]
+    #[case("nil", Null(Span(0, 3)))]
+    #[case("nullable", Reference("nullable", Span(0, 8)))]
+    #[case(" ~5", Operator("~", Span(1, 2)))]
+    #[case(" ??= 5", Operator("??=", Span(1, 4)))]
+    #[case("?? 5", Operator("??", Span(0, 2)))]
+    #[case("//2", Operator("//", Span(0, 2)))]
+    #[case("? 5", Operator("?", Span(0, 1)))]
+    #[case("?-1", Operator("?", Span(0, 1)))]
+    #[case("~~0", Operator("~", Span(0, 1)))]
     #[case(" beginWith", Operator("beginWith", Span(1, 10)))]
     #[case(" endWith", Operator("endWith", Span(1, 8)))]
     fn test_other(#[case] input: &str, #[case] output: Token) {
@@ -377,6 +492,13 @@ mod tests {
     #[rstest]
     #[case(" 'dsfasdfdsa' ", "dsfasdfdsa", 1, 13)]
     #[case("\"dffd\"", "dffd", 0, 6)]
+    #[case(
+        "'line1\nactual newline\nline2'",
+        "line1\nactual newline\nline2",
+        0,
+        28
+    )]
+    #[case(" \"a\r\n\tb\" ", "a\r\n\tb", 1, 8)]
     fn test_string(
         #[case] input: &str,
         #[case] value: &str,
@@ -393,6 +515,7 @@ mod tests {
     #[case(" d09f_5 ", Reference("d09f_5", Span(1, 7)))]
     #[case(" d09f_5() ", Function("d09f_5", Span(1, 7)))]
     #[case(" d09f_>", Reference("d09f_", Span(1, 6)))]
+    #[case("_1", Reference("_1", Span(0, 2)))]
     fn test_reference_function(#[case] input: &str, #[case] output: Token) {
         init();
         let mut tokenizer = Tokenizer::new(input);
@@ -403,10 +526,137 @@ mod tests {
     #[rstest]
     #[case("\"jajd'")]
     #[case("0e.3")]
+    #[case("1_")]
+    #[case("1__0")]
+    #[case("1_.5")]
+    #[case("1._5")]
+    #[case("0xG1")]
+    #[case("0x")]
+    #[case("0b102")]
+    #[case("0x_FF")]
     fn test_err(#[case] input: &str) {
         init();
         let mut tokenizer = Tokenizer::new(input);
         let ans = tokenizer.next();
         assert!(ans.is_err())
     }
+
+    #[test]
+    fn test_multi_line_string() {
+        init();
+        let mut tokenizer = Tokenizer::new("'a\nb' +\n1");
+        assert_eq!(tokenizer.next().unwrap(), String("a\nb", Span(0, 5)));
+        assert_eq!(tokenizer.next().unwrap(), Operator("+", Span(6, 7)));
+        assert_eq!(tokenizer.next().unwrap(), Number(1.into(), Span(8, 9)));
+
+        let mut tokenizer = Tokenizer::new("1 + 'a\nb");
+        tokenizer.next().unwrap();
+        tokenizer.next().unwrap();
+        assert!(matches!(
+            tokenizer.next(),
+            Err(crate::error::Error::UnterminatedString(8))
+        ));
+    }
+
+    #[test]
+    fn test_registered_op_longest_match() {
+        use crate::operator::{InfixOpAssociativity, InfixOpManager, InfixOpType};
+        use crate::value::Value;
+        use std::sync::Arc;
+        init();
+        InfixOpManager::new().register(
+            "=>>",
+            45,
+            InfixOpType::CALC,
+            InfixOpAssociativity::RIGHT,
+            Arc::new(|left, right| Ok(Value::from(!left.bool()? || right.bool()?))),
+        );
+        let mut tokenizer = Tokenizer::new("a=>>b==c=d=>>=e");
+        let mut ops = vec![];
+        loop {
+            match tokenizer.next().unwrap() {
+                Operator(op, _) => ops.push(op),
+                EOF => break,
+                _ => (),
+            }
+        }
+        assert_eq!(ops, vec!["=>>", "==", "=", "=>>", "="]);
+    }
+
+    #[rstest]
+    #[case("not-a", vec!["not", "-", "a"])]
+    #[case("a mod-3", vec!["a", "mod", "-", "3"])]
+    #[case("nota-1", vec!["nota", "-", "1"])]
+    #[case("a in[1]", vec!["a", "in", "[", "1", "]"])]
+    fn test_word_op_boundary(#[case] input: &str, #[case] output: Vec<&str>) {
+        init();
+        let mut tokenizer = Tokenizer::new(input);
+        let mut tokens = vec![];
+        loop {
+            match tokenizer.next().unwrap() {
+                EOF => break,
+                token => tokens.push(token.string()),
+            }
+        }
+        assert_eq!(tokens, output);
+    }
+
+    #[test]
+    fn test_long_operator_run() {
+        init();
+        let input = "!".repeat(20_000) + "?" + &"=".repeat(20_000);
+        let mut tokenizer = Tokenizer::new(&input);
+        let mut count = 0;
+        loop {
+            match tokenizer.next().unwrap() {
+                Operator(_, Span(start, end)) => {
+                    assert!(end - start <= 2);
+                    count += 1;
+                }
+                EOF => break,
+                _ => unreachable!(),
+            }
+        }
+        assert!(count >= 30_000);
+    }
+
+    #[test]
+    fn test_backtick_string() {
+        init();
+        let mut tokenizer = Tokenizer::new("`it's \"raw\" \\d+` + `{\"a\":\n1}`");
+        assert_eq!(
+            tokenizer.next().unwrap(),
+            String(r#"it's "raw" \d+"#, Span(0, 16))
+        );
+        assert_eq!(tokenizer.next().unwrap(), Operator("+", Span(17, 18)));
+        assert_eq!(
+            tokenizer.next().unwrap(),
+            String("{\"a\":\n1}", Span(19, 29))
+        );
+
+        let mut tokenizer = Tokenizer::new("`a'b\"");
+        assert!(matches!(
+            tokenizer.next(),
+            Err(crate::error::Error::UnterminatedString(5))
+        ));
+    }
+
+    #[rstest]
+    #[case(0, 1, 1)]
+    #[case(6, 1, 7)]
+    #[case(7, 2, 1)]
+    #[case(11, 2, 5)]
+    #[case(13, 2, 6)]
+    #[case(14, 2, 7)]
+    #[case(18, 3, 2)]
+    #[case(100, 3, 6)]
+    fn test_line_col(#[case] offset: usize, #[case] line: usize, #[case] col: usize) {
+        let tokenizer = Tokenizer::new("a = 1;\nb = 'é';\na + b");
+        assert_eq!(tokenizer.line_col(offset), (line, col));
+    }
+
+    #[test]
+    fn test_line_col_inside_char() {
+        assert_eq!(Tokenizer::new("é").line_col(1), (1, 1));
+    }
 }
@@ -1,9 +1,16 @@
 use crate::define::Result;
 use crate::error::Error;
+use crate::function::InnerFunction;
 use rust_decimal::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 
-#[derive(Clone, PartialEq, Debug)]
+/// Variants can be added by cargo features, so matches outside this crate need
+/// a wildcard arm.
+#[derive(Clone)]
+#[non_exhaustive]
 pub enum Value {
     String(String),
     Number(Decimal),
@@ -11,33 +18,38 @@ pub enum Value {
     List(Vec<Value>),
     Map(Vec<(Value, Value)>),
     None,
+    /// A function passed around as a value, e.g. to `map`. A reference to a
+    /// registered function's name evaluates to one.
+    Function(Arc<InnerFunction>),
+    /// Milliseconds since the unix epoch, in UTC.
+    #[cfg(feature = "chrono")]
+    DateTime(i64),
+    /// A time span in seconds.
+    #[cfg(feature = "chrono")]
+    Duration(i64),
 }
 
 #[cfg(not(tarpaulin_include))]
 impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(&|_, s| s))
+    }
+}
+
+impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::String(val) => write!(f, "value string: {}", val.clone()),
-            Self::Number(val) => write!(f, "value number: {}", val.clone()),
-            Self::Bool(val) => write!(f, "value bool: {}", val.clone()),
-            Self::List(values) => {
-                let mut s = String::from("[");
-                for value in values {
-                    s.push_str(format!("{},", value.clone()).as_str());
-                }
-                s.push_str("]");
-                write!(f, "value list: {}", s)
-            }
-            Self::Map(m) => {
-                let mut s = String::from("{");
-                for (k, v) in m {
-                    s.push_str(format!("key: {},", k.clone()).as_str());
-                    s.push_str(format!("value: {}; ", v.clone()).as_str());
-                }
-                s.push_str("}");
-                write!(f, "value map: {}", s)
-            }
+            Self::String(val) => f.debug_tuple("String").field(val).finish(),
+            Self::Number(val) => f.debug_tuple("Number").field(val).finish(),
+            Self::Bool(val) => f.debug_tuple("Bool").field(val).finish(),
+            Self::List(values) => f.debug_tuple("List").field(values).finish(),
+            Self::Map(m) => f.debug_tuple("Map").field(m).finish(),
             Self::None => write!(f, "None"),
+            Self::Function(func) => write!(f, "Function({:p})", Arc::as_ptr(func)),
+            #[cfg(feature = "chrono")]
+            Self::DateTime(val) => f.debug_tuple("DateTime").field(val).finish(),
+            #[cfg(feature = "chrono")]
+            Self::Duration(val) => f.debug_tuple("Duration").field(val).finish(),
         }
     }
 }
@@ -60,9 +72,32 @@ impl From<bool> for Value {
     }
 }
 
-impl From<Vec<Value>> for Value {
-    fn from(value: Vec<Value>) -> Self {
-        Value::List(value)
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Self {
+        Value::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+// The entries are sorted by key, so the map doesn't depend on the hash order.
+impl<V: Into<Value>> From<HashMap<String, V>> for Value {
+    fn from(value: HashMap<String, V>) -> Self {
+        let mut m: Vec<(String, V)> = value.into_iter().collect();
+        m.sort_by(|a, b| a.0.cmp(&b.0));
+        Value::Map(m.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+// Fails with `ShouldBeString` on keys that aren't strings. Of repeated keys the
+// first one is kept, like indexing the map does.
+impl TryFrom<Value> for HashMap<String, Value> {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        let mut ans = HashMap::new();
+        for (k, v) in value.map()? {
+            ans.entry(k.string()?).or_insert(v);
+        }
+        Ok(ans)
     }
 }
 
@@ -72,7 +107,171 @@ impl From<Decimal> for Value {
     }
 }
 
+impl From<Arc<InnerFunction>> for Value {
+    fn from(value: Arc<InnerFunction>) -> Self {
+        Value::Function(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Value::None,
+        }
+    }
+}
+
+// Maps compare regardless of entry order. Lengths are checked first so that
+// unequal maps are usually rejected without looking at their entries.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::List(a), Self::List(b)) => a == b,
+            (Self::Map(a), Self::Map(b)) => {
+                a.len() == b.len() && (a == b || cmp_entries(a, b) == Ordering::Equal)
+            }
+            (Self::None, Self::None) => true,
+            (Self::Function(a), Self::Function(b)) => Arc::ptr_eq(a, b),
+            #[cfg(feature = "chrono")]
+            (Self::DateTime(a), Self::DateTime(b)) => a == b,
+            #[cfg(feature = "chrono")]
+            (Self::Duration(a), Self::Duration(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+// Maps are compared as multisets of entries, by sorting the entries of both.
+fn cmp_entries(a: &[(Value, Value)], b: &[(Value, Value)]) -> Ordering {
+    fn cmp_entry(a: &(Value, Value), b: &(Value, Value)) -> Ordering {
+        a.0.total_cmp(&b.0).then_with(|| a.1.total_cmp(&b.1))
+    }
+    fn sorted(m: &[(Value, Value)]) -> Vec<&(Value, Value)> {
+        let mut entries: Vec<&(Value, Value)> = m.iter().collect();
+        entries.sort_by(|x, y| cmp_entry(x, y));
+        entries
+    }
+    let (a, b) = (sorted(a), sorted(b));
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| cmp_entry(x, y))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+// Only numbers, strings, dates and durations are ordered, and only against their own type.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a.partial_cmp(b),
+            (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            #[cfg(feature = "chrono")]
+            (Self::DateTime(a), Self::DateTime(b)) => a.partial_cmp(b),
+            #[cfg(feature = "chrono")]
+            (Self::Duration(a), Self::Duration(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
 impl Value {
+    /// Renders the value the way `Display` does, passing each scalar through
+    /// `paint` along with the ANSI color code of its type.
+    fn render(&self, paint: &dyn Fn(&str, String) -> String) -> String {
+        match self {
+            Self::String(val) => format!("value string: {}", paint("32", val.clone())),
+            Self::Number(val) => format!("value number: {}", paint("36", val.to_string())),
+            Self::Bool(val) => format!("value bool: {}", paint("33", val.to_string())),
+            Self::List(values) => {
+                let values: String = values
+                    .iter()
+                    .map(|value| format!("{},", value.render(paint)))
+                    .collect();
+                format!("value list: [{}]", values)
+            }
+            Self::Map(m) => {
+                let entries: String = m
+                    .iter()
+                    .map(|(k, v)| format!("key: {},value: {}; ", k.render(paint), v.render(paint)))
+                    .collect();
+                format!("value map: {{{}}}", entries)
+            }
+            Self::None => paint("90", "None".to_string()),
+            Self::Function(_) => format!("value {}", paint("35", "function".to_string())),
+            #[cfg(feature = "chrono")]
+            Self::DateTime(val) => format!("value datetime: {}", paint("34", val.to_string())),
+            #[cfg(feature = "chrono")]
+            Self::Duration(val) => format!("value duration: {}", paint("34", val.to_string())),
+        }
+    }
+
+    /// Renders the value like `Display`, with ANSI colors for terminals:
+    /// strings green, numbers cyan, bools yellow and `None` grey.
+    ///
+    /// ## Usage
+    ///
+    /// ```rust
+    /// use expression_engine::Value;
+    /// let v = Value::from("a");
+    /// assert_eq!(v.to_colored_string(), "value string: \x1b[32ma\x1b[0m");
+    /// ```
+    #[cfg(feature = "color")]
+    pub fn to_colored_string(&self) -> String {
+        self.render(&|code, s| format!("\x1b[{}m{}\x1b[0m", code, s))
+    }
+
+    // A total order over all values that agrees with `==`, used to compare maps
+    // regardless of entry order. Values of different types are ordered by type.
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            (Self::Number(a), Self::Number(b)) => a.cmp(b),
+            (Self::Bool(a), Self::Bool(b)) => a.cmp(b),
+            (Self::List(a), Self::List(b)) => a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| x.total_cmp(y))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (Self::Map(a), Self::Map(b)) => cmp_entries(a, b),
+            (Self::Function(a), Self::Function(b)) => Arc::as_ptr(a)
+                .cast::<()>()
+                .cmp(&Arc::as_ptr(b).cast::<()>()),
+            #[cfg(feature = "chrono")]
+            (Self::DateTime(a), Self::DateTime(b)) => a.cmp(b),
+            #[cfg(feature = "chrono")]
+            (Self::Duration(a), Self::Duration(b)) => a.cmp(b),
+            _ => self.type_name().cmp(other.type_name()),
+        }
+    }
+
+    /// Returns the name of the value's type, which is one of `number`, `string`,
+    /// `bool`, `list`, `map`, `none` and `function`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::Number(_) => "number",
+            Self::Bool(_) => "bool",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+            Self::None => "none",
+            Self::Function(_) => "function",
+            #[cfg(feature = "chrono")]
+            Self::DateTime(_) => "datetime",
+            #[cfg(feature = "chrono")]
+            Self::Duration(_) => "duration",
+        }
+    }
+
+    /// Whether the value is of the type with the given `type_name`.
+    pub fn matches_type(&self, type_name: &str) -> bool {
+        self.type_name() == type_name
+    }
+
     pub fn decimal(self) -> Result<rust_decimal::Decimal> {
         match self {
             Self::Number(val) => Ok(val),
@@ -96,20 +295,20 @@ impl Value {
 
     pub fn integer(self) -> Result<i64> {
         match self {
-            Self::Number(val) => val
-                .to_string()
-                .parse()
-                .map_or(Err(Error::InvalidInteger), |num| Ok(num)),
+            Self::Number(val) => {
+                if !val.fract().is_zero() {
+                    return Err(Error::NotInteger(val.to_string()));
+                }
+                val.to_i64()
+                    .ok_or_else(|| Error::IntegerOutOfRange(val.to_string()))
+            }
             _ => Err(Error::InvalidInteger),
         }
     }
 
     pub fn float(self) -> Result<f64> {
         match self {
-            Self::Number(val) => val
-                .to_string()
-                .parse()
-                .map_or(Err(Error::InvalidFloat), |num| Ok(num)),
+            Self::Number(val) => val.to_string().parse().map_or(Err(Error::InvalidFloat), Ok),
             _ => Err(Error::InvalidFloat),
         }
     }
@@ -120,6 +319,185 @@ impl Value {
             _ => Err(Error::ShouldBeList()),
         }
     }
+
+    pub fn map(self) -> Result<Vec<(Value, Value)>> {
+        match self {
+            Self::Map(m) => Ok(m),
+            _ => Err(Error::ShouldBeMap()),
+        }
+    }
+
+    /// Borrowing counterparts of the accessors above, for inspecting a value
+    /// without consuming or cloning it.
+    pub fn as_number(&self) -> Option<Decimal> {
+        match self {
+            Self::Number(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Self::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Returns the canonical form of the value, suitable for hashing or serializing
+    /// as a cache key: numbers are normalized so `2.0` equals `2`, and map entries
+    /// are sorted by key. List order is kept.
+    pub fn canonicalize(&self) -> Value {
+        match self {
+            Self::Number(val) => Self::Number(val.normalize()),
+            Self::List(values) => Self::List(values.iter().map(Value::canonicalize).collect()),
+            Self::Map(m) => {
+                let mut m: Vec<(Value, Value)> = m
+                    .iter()
+                    .map(|(k, v)| (k.canonicalize(), v.canonicalize()))
+                    .collect();
+                // Keys of different types have no natural order, their debug form gives a stable one.
+                m.sort_by_cached_key(|(k, _)| format!("{:?}", k));
+                Self::Map(m)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// The comparisons below back the built-in comparison operators, so custom
+    /// operators can share their semantics. Ordering a pair that isn't two numbers
+    /// or two strings fails with `Error::NotComparable`.
+    pub fn lt(&self, other: &Value) -> Result<Value> {
+        Ok(Value::from(self.compare(other)?.is_lt()))
+    }
+
+    pub fn le(&self, other: &Value) -> Result<Value> {
+        Ok(Value::from(self.compare(other)?.is_le()))
+    }
+
+    pub fn gt(&self, other: &Value) -> Result<Value> {
+        Ok(Value::from(self.compare(other)?.is_gt()))
+    }
+
+    pub fn ge(&self, other: &Value) -> Result<Value> {
+        Ok(Value::from(self.compare(other)?.is_ge()))
+    }
+
+    pub fn eq_value(&self, other: &Value) -> Result<Value> {
+        Ok(Value::from(self == other))
+    }
+
+    pub fn ne_value(&self, other: &Value) -> Result<Value> {
+        Ok(Value::from(self != other))
+    }
+
+    pub(crate) fn compare(&self, other: &Value) -> Result<Ordering> {
+        self.partial_cmp(other).ok_or_else(|| {
+            Error::NotComparable(self.type_name().to_string(), other.type_name().to_string())
+        })
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl TryFrom<serde_json::Value> for Value {
+    type Error = Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self> {
+        use serde_json::Value as Json;
+        Ok(match value {
+            Json::Null => Value::None,
+            Json::Bool(value) => Value::Bool(value),
+            Json::Number(value) => {
+                let s = value.to_string();
+                Value::Number(
+                    Decimal::from_str(&s)
+                        .or_else(|_| Decimal::from_scientific(&s))
+                        .map_err(|_| Error::InvalidNumber(s))?,
+                )
+            }
+            Json::String(value) => Value::String(value),
+            Json::Array(values) => Value::List(
+                values
+                    .into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_>>()?,
+            ),
+            Json::Object(m) => Value::Map(
+                m.into_iter()
+                    .map(|(k, v)| Ok((Value::String(k), Value::try_from(v)?)))
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl Value {
+    /// Parses JSON text into a value, see `TryFrom<serde_json::Value>`.
+    pub(crate) fn from_json_str(s: &str) -> Result<Self> {
+        let json = serde_json::from_str::<serde_json::Value>(s)
+            .map_err(|err| Error::Custom(format!("invalid json: {}", err)))?;
+        Value::try_from(json)
+    }
+
+    /// Converts the value into JSON. Integral numbers become JSON integers when
+    /// they fit, and non-string map keys are written in their literal form.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value as Json;
+        match self {
+            Self::String(value) => Json::String(value.clone()),
+            Self::Number(value) => {
+                if value.fract().is_zero() {
+                    if let Some(n) = value.to_i64() {
+                        return Json::from(n);
+                    }
+                }
+                value
+                    .to_f64()
+                    .and_then(serde_json::Number::from_f64)
+                    .map_or(Json::Null, Json::Number)
+            }
+            Self::Bool(value) => Json::Bool(*value),
+            Self::List(values) => Json::Array(values.iter().map(|v| v.to_json()).collect()),
+            Self::Map(m) => {
+                Json::Object(m.iter().map(|(k, v)| (k.json_key(), v.to_json())).collect())
+            }
+            Self::None | Self::Function(_) => Json::Null,
+            #[cfg(feature = "chrono")]
+            Self::DateTime(value) => chrono::DateTime::from_timestamp_millis(*value)
+                .map_or(Json::Null, |datetime| Json::String(datetime.to_rfc3339())),
+            #[cfg(feature = "chrono")]
+            Self::Duration(value) => Json::from(*value),
+        }
+    }
+
+    fn json_key(&self) -> String {
+        match self {
+            Self::String(value) => value.clone(),
+            Self::Number(value) => value.to_string(),
+            _ => self.to_json().to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for Value {
+    fn from(value: chrono::DateTime<chrono::Utc>) -> Self {
+        Value::DateTime(value.timestamp_millis())
+    }
 }
 
 macro_rules! impl_value_from_for_number {
@@ -148,3 +526,362 @@ impl_value_from_for_number!(
     [f64, from_f64],
     [f32, from_f32]
 );
+
+macro_rules! impl_value_from_for_tuple {
+    ($(($($name:ident),+)),+) => {
+        $(
+            impl<$($name: Into<Value>),+> From<($($name,)+)> for Value {
+                #[allow(non_snake_case)]
+                fn from(($($name,)+): ($($name,)+)) -> Self {
+                    Value::List(vec![$($name.into()),+])
+                }
+            }
+        )+
+    };
+}
+
+impl_value_from_for_tuple!(
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F)
+);
+
+// Each conversion fails with the same error as the matching accessor, e.g.
+// `i64::try_from` rejects fractions like `integer` does.
+macro_rules! impl_try_from_value {
+    ($([$target_type:ty, $method_name: ident]),+) => {
+        $(
+            impl TryFrom<Value> for $target_type {
+                type Error = Error;
+
+                fn try_from(value: Value) -> Result<Self> {
+                    value.$method_name()
+                }
+            }
+        )+
+    };
+}
+
+impl_try_from_value!(
+    [Decimal, decimal],
+    [String, string],
+    [bool, bool],
+    [i64, integer],
+    [f64, float],
+    [Vec<Value>, list]
+);
+
+#[cfg(test)]
+mod tests {
+    use super::Value;
+    use crate::error::Error;
+    use rstest::rstest;
+    use rust_decimal::prelude::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_vec_conversion() {
+        let value = Value::from(vec![1, 2, 3]);
+        assert_eq!(value, Value::List(vec![1.into(), 2.into(), 3.into()]));
+        let back: Vec<Value> = value.try_into().unwrap();
+        assert_eq!(
+            back.into_iter()
+                .map(|v| v.integer().unwrap())
+                .collect::<Vec<i64>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            Value::from(vec!["a", "b"]),
+            Value::List(vec!["a".into(), "b".into()])
+        );
+    }
+
+    #[test]
+    fn test_hash_map_conversion() {
+        let m = HashMap::from([
+            ("b".to_string(), Value::from(2)),
+            ("a".to_string(), Value::from(vec![true])),
+        ]);
+        let value = Value::from(m.clone());
+        assert_eq!(
+            value,
+            Value::Map(vec![
+                ("a".into(), Value::List(vec![true.into()])),
+                ("b".into(), 2.into())
+            ])
+        );
+        assert_eq!(HashMap::<String, Value>::try_from(value).unwrap(), m);
+        let keyed = Value::Map(vec![("a".into(), 1.into()), (1.into(), 2.into())]);
+        assert!(matches!(
+            HashMap::<String, Value>::try_from(keyed),
+            Err(Error::ShouldBeString())
+        ));
+        assert!(matches!(
+            HashMap::<String, Value>::try_from(Value::from(1)),
+            Err(Error::ShouldBeMap())
+        ));
+    }
+
+    #[rstest]
+    #[case(Some(5), Value::from(5))]
+    #[case(None, Value::None)]
+    fn test_from_option(#[case] input: Option<i64>, #[case] output: Value) {
+        assert_eq!(Value::from(input), output);
+    }
+
+    #[test]
+    fn test_try_into() {
+        assert_eq!(i64::try_from(Value::from(3)).unwrap(), 3);
+        assert_eq!(f64::try_from(Value::from(1.5)).unwrap(), 1.5);
+        assert!(bool::try_from(Value::from(true)).unwrap());
+        assert_eq!(String::try_from(Value::from("a")).unwrap(), "a");
+        assert_eq!(
+            Vec::<Value>::try_from(Value::from(vec![Value::from(1)])).unwrap(),
+            vec![Value::from(1)]
+        );
+        assert!(matches!(
+            i64::try_from(Value::from(1.5)),
+            Err(Error::NotInteger(_))
+        ));
+        assert!(matches!(
+            bool::try_from(Value::from(1)),
+            Err(Error::ShouldBeBool())
+        ));
+    }
+
+    #[cfg(feature = "color")]
+    #[rstest]
+    #[case(Value::from("a"))]
+    #[case(Value::from(1.5))]
+    #[case(Value::None)]
+    #[case(Value::from(vec![Value::from(true), Value::from(vec![Value::None])]))]
+    #[case(Value::Map(vec![(Value::from("k"), Value::from(vec![Value::from(2)]))]))]
+    fn test_to_colored_string(#[case] input: Value) {
+        let colored = input.to_colored_string();
+        assert!(colored.contains('\x1b'));
+        let mut plain = String::new();
+        let mut chars = colored.chars();
+        while let Some(ch) = chars.next() {
+            if ch == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                plain.push(ch);
+            }
+        }
+        assert_eq!(plain, input.to_string());
+    }
+
+    #[rstest]
+    #[case("3.0", 3)]
+    #[case("-7", -7)]
+    #[case("9223372036854775807", i64::MAX)]
+    fn test_integer(#[case] input: &str, #[case] output: i64) {
+        let value = Value::from(Decimal::from_str(input).unwrap());
+        assert_eq!(value.integer().unwrap(), output);
+    }
+
+    #[test]
+    fn test_integer_error() {
+        let fractional = Value::from(Decimal::from_str("3.5").unwrap());
+        assert!(matches!(fractional.integer(), Err(Error::NotInteger(s)) if s == "3.5"));
+        let huge = Value::from(Decimal::from_str("9223372036854775808").unwrap());
+        assert!(matches!(huge.integer(), Err(Error::IntegerOutOfRange(_))));
+        assert!(matches!(
+            Value::from("3").integer(),
+            Err(Error::InvalidInteger)
+        ));
+    }
+
+    #[test]
+    fn test_from_tuple() {
+        assert_eq!(
+            Value::from((1i64, "a", true)),
+            Value::List(vec![Value::from(1), Value::from("a"), Value::from(true)])
+        );
+        assert_eq!(
+            Value::from((2, (3.5, None::<i64>))),
+            Value::List(vec![
+                Value::from(2),
+                Value::List(vec![Value::from(3.5), Value::None])
+            ])
+        );
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_json() {
+        let json = serde_json::json!({
+            "a": 1,
+            "b": [1.5, "x", true, null],
+            "c": {"d": [{"e": -2}], "f": 1e3}
+        });
+        let value = Value::try_from(json.clone()).unwrap();
+        assert_eq!(
+            value,
+            Value::Map(vec![
+                (Value::from("a"), Value::from(1)),
+                (
+                    Value::from("b"),
+                    Value::List(vec![
+                        Value::from(1.5),
+                        Value::from("x"),
+                        Value::from(true),
+                        Value::None
+                    ])
+                ),
+                (
+                    Value::from("c"),
+                    Value::Map(vec![
+                        (
+                            Value::from("d"),
+                            Value::List(vec![Value::Map(vec![(
+                                Value::from("e"),
+                                Value::from(-2)
+                            )])])
+                        ),
+                        (Value::from("f"), Value::from(1000)),
+                    ])
+                ),
+            ])
+        );
+        assert_eq!(
+            value.to_json(),
+            serde_json::json!({
+                "a": 1,
+                "b": [1.5, "x", true, null],
+                "c": {"d": [{"e": -2}], "f": 1000}
+            })
+        );
+        assert_eq!(
+            Value::Map(vec![(Value::from(1), Value::from(true))]).to_json(),
+            serde_json::json!({"1": true})
+        );
+        assert!(matches!(
+            Value::try_from(serde_json::json!({"a": 1e30})),
+            Err(Error::InvalidNumber(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(1.into(), 2.into(), [true, true, false, false, false, true])]
+    #[case(2.into(), 2.into(), [false, true, false, true, true, false])]
+    #[case(3.5.into(), 2.into(), [false, false, true, true, false, true])]
+    #[case("a".into(), "b".into(), [true, true, false, false, false, true])]
+    #[case("b".into(), "b".into(), [false, true, false, true, true, false])]
+    #[case("ba".into(), "b".into(), [false, false, true, true, false, true])]
+    fn test_compare(#[case] a: Value, #[case] b: Value, #[case] output: [bool; 6]) {
+        let ans = [
+            a.lt(&b).unwrap(),
+            a.le(&b).unwrap(),
+            a.gt(&b).unwrap(),
+            a.ge(&b).unwrap(),
+            a.eq_value(&b).unwrap(),
+            a.ne_value(&b).unwrap(),
+        ];
+        assert_eq!(ans, output.map(Value::from));
+    }
+
+    #[rstest]
+    #[case(1.into(), "1".into())]
+    #[case("a".into(), 1.into())]
+    #[case(true.into(), false.into())]
+    #[case(Value::None, 1.into())]
+    fn test_compare_error(#[case] a: Value, #[case] b: Value) {
+        for ans in [a.lt(&b), a.le(&b), a.gt(&b), a.ge(&b)] {
+            assert!(matches!(ans, Err(Error::NotComparable(_, _))));
+        }
+        assert_eq!(a.eq_value(&b).unwrap(), false.into());
+        assert_eq!(a.ne_value(&b).unwrap(), true.into());
+    }
+
+    #[rstest]
+    #[case(
+        Value::Map(vec![("b".into(), 2.into()), ("a".into(), 1.into())]),
+        Value::Map(vec![("a".into(), 1.into()), ("b".into(), 2.into())])
+    )]
+    #[case(Value::from(Decimal::from_str("2.0").unwrap()), 2.into())]
+    #[case(
+        Value::List(vec![Value::from(Decimal::from_str("1.50").unwrap()), "x".into()]),
+        Value::List(vec![Value::from(1.5), "x".into()])
+    )]
+    #[case(
+        Value::Map(vec![
+            (2.into(), Value::Map(vec![("y".into(), true.into()), ("x".into(), Value::None)])),
+            (Value::from(Decimal::from_str("1.0").unwrap()), "a".into()),
+        ]),
+        Value::Map(vec![
+            (1.into(), "a".into()),
+            (2.into(), Value::Map(vec![("x".into(), Value::None), ("y".into(), true.into())])),
+        ])
+    )]
+    fn test_canonicalize(#[case] a: Value, #[case] b: Value) {
+        assert_eq!(a.canonicalize(), b.canonicalize());
+        assert_eq!(
+            format!("{:?}", a.canonicalize()),
+            format!("{:?}", b.canonicalize())
+        );
+        assert_eq!(a.canonicalize(), a.canonicalize().canonicalize());
+    }
+
+    #[rstest]
+    #[case(
+        Value::Map(vec![("a".into(), 1.into()), ("b".into(), 2.into())]),
+        Value::Map(vec![("b".into(), 2.into()), ("a".into(), 1.into())]),
+        true
+    )]
+    #[case(
+        Value::Map(vec![("a".into(), Value::Map(vec![(1.into(), 2.into()), (3.into(), 4.into())]))]),
+        Value::Map(vec![("a".into(), Value::Map(vec![(3.into(), 4.into()), (1.into(), 2.into())]))]),
+        true
+    )]
+    #[case(
+        Value::Map(vec![("a".into(), 1.into()), ("b".into(), 2.into())]),
+        Value::Map(vec![("a".into(), 1.into())]),
+        false
+    )]
+    #[case(
+        Value::Map(vec![("a".into(), 1.into()), ("a".into(), 1.into())]),
+        Value::Map(vec![("a".into(), 1.into()), ("b".into(), 1.into())]),
+        false
+    )]
+    #[case(
+        Value::List(vec![1.into(), 2.into()]),
+        Value::List(vec![2.into(), 1.into()]),
+        false
+    )]
+    #[case(Value::List(vec![]), Value::Map(vec![]), false)]
+    fn test_eq(#[case] a: Value, #[case] b: Value, #[case] output: bool) {
+        assert_eq!(a == b, output);
+        assert_eq!(b == a, output);
+    }
+
+    #[test]
+    fn test_canonicalize_keeps_list_order() {
+        let list = Value::List(vec![2.into(), 1.into()]);
+        assert_eq!(list.canonicalize(), list);
+        assert_ne!(list.canonicalize(), Value::List(vec![1.into(), 2.into()]));
+    }
+
+    #[test]
+    fn test_as_accessors() {
+        let number = Value::from(1.5);
+        let string = Value::from("a");
+        let list = Value::List(vec![1.into(), "b".into()]);
+        assert_eq!(number.as_number(), Some(Decimal::from_str("1.5").unwrap()));
+        assert_eq!(string.as_number(), None);
+        assert_eq!(string.as_str(), Some("a"));
+        assert_eq!(number.as_str(), None);
+        assert_eq!(Value::from(true).as_bool(), Some(true));
+        assert_eq!(Value::None.as_bool(), None);
+        assert_eq!(list.as_list(), Some(&[1.into(), "b".into()][..]));
+        assert_eq!(Value::Map(vec![]).as_list(), None);
+    }
+
+    #[test]
+    fn test_from_option_string() {
+        assert_eq!(Value::from(Some("a")), Value::from("a"));
+        assert_eq!(Value::from(None::<String>), Value::None);
+    }
+}